@@ -0,0 +1,44 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::hint::black_box;
+
+static SAMPLE: &str = "在LeetCode上刷题,用Rust写了3个solution.他说:\"这很简单\"...然后（笑）看了https://example.com/a?b=c的文档";
+
+/// a line dense with quotes and the spaces around them, the quote passes used to remove the
+/// spaces one at a time
+static QUOTES: &str = "他说 \"好\" 和 ‘是’ ,she said \" yes \" ";
+
+/// a line dense with brackets, each repetition leaves one open so the punctuation passes used to
+/// scan to the end of the line for every bracket
+static BRACKETS: &str = "中（注(a）和(b)（";
+
+/// the time per byte should stay flat as the input grows, for many lines and for a single one
+fn scaling(c: &mut Criterion) {
+    let mut group = c.benchmark_group("normalize");
+    for size in [16, 64, 256, 1024] {
+        let lines = vec![SAMPLE; size].join("\n");
+        group.throughput(Throughput::Bytes(lines.len() as u64));
+        group.bench_with_input(BenchmarkId::new("lines", size), &lines, |b, content| {
+            b.iter(|| chineseword::normalize(black_box(content.as_str())))
+        });
+        let line = vec![SAMPLE; size].join("");
+        group.bench_with_input(BenchmarkId::new("line", size), &line, |b, content| {
+            b.iter(|| chineseword::normalize(black_box(content.as_str())))
+        });
+        let quotes = vec![QUOTES; size].join("");
+        group.throughput(Throughput::Bytes(quotes.len() as u64));
+        group.bench_with_input(BenchmarkId::new("quotes", size), &quotes, |b, content| {
+            b.iter(|| chineseword::normalize(black_box(content.as_str())))
+        });
+        let brackets = vec![BRACKETS; size].join("");
+        group.throughput(Throughput::Bytes(brackets.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::new("brackets", size),
+            &brackets,
+            |b, content| b.iter(|| chineseword::normalize(black_box(content.as_str()))),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, scaling);
+criterion_main!(benches);
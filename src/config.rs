@@ -0,0 +1,384 @@
+use crate::{Locale, NormalizeOptions, ZhPeriod, ZhQuote};
+use globset::Glob;
+use serde::{de, Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// the name of the config files looked up by [`Config::resolve`]
+pub static CONFIG_FILE_NAME: &str = ".chineseword.toml";
+
+/// options read from a toml file, every key is optional:
+///
+/// ```toml
+/// root = true               # do not look further up for config files
+/// locale = "zh-Hant-TW"
+/// zh_period = "empty"       # empty, dot or en-dot
+/// zh_quote = "rect"         # curly, rect, straight or tex
+/// zh_ellipsis = "⋯⋯"
+/// en_ellipsis = "..."
+/// minor_space = true
+/// preserve_layout = true
+///
+/// [rules]                   # rules and passes turned on or off by id prefix
+/// "space/minor" = false
+/// "space/minor/zh-letter+en-letter" = true
+///
+/// [[overrides]]             # options for the files matching the globs, relative to the file
+/// files = ["docs/en/**"]
+/// minor_space = false
+/// ```
+///
+/// the locale profile is applied first, the other keys override it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub root: bool,
+    #[serde(default, deserialize_with = "parse")]
+    pub locale: Option<Locale>,
+    #[serde(default, deserialize_with = "parse")]
+    pub zh_period: Option<ZhPeriod>,
+    #[serde(default, deserialize_with = "parse")]
+    pub zh_quote: Option<ZhQuote>,
+    pub zh_ellipsis: Option<String>,
+    pub en_ellipsis: Option<String>,
+    pub minor_space: Option<bool>,
+    pub preserve_layout: Option<bool>,
+    #[serde(default)]
+    pub rules: BTreeMap<String, bool>,
+    #[serde(default)]
+    pub overrides: Vec<Override>,
+}
+
+/// options applied on top of the config for the files matching any of the globs
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(from = "OverrideKeys")]
+pub struct Override {
+    pub files: Vec<String>,
+    pub config: Config,
+}
+
+/// the keys an override may set, listed here since `deny_unknown_fields` is lost on a flattened
+/// config
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OverrideKeys {
+    files: Vec<String>,
+    #[serde(default, deserialize_with = "parse")]
+    locale: Option<Locale>,
+    #[serde(default, deserialize_with = "parse")]
+    zh_period: Option<ZhPeriod>,
+    #[serde(default, deserialize_with = "parse")]
+    zh_quote: Option<ZhQuote>,
+    zh_ellipsis: Option<String>,
+    en_ellipsis: Option<String>,
+    minor_space: Option<bool>,
+    preserve_layout: Option<bool>,
+    #[serde(default)]
+    rules: BTreeMap<String, bool>,
+}
+
+impl From<OverrideKeys> for Override {
+    fn from(keys: OverrideKeys) -> Self {
+        Override {
+            files: keys.files,
+            config: Config {
+                locale: keys.locale,
+                zh_period: keys.zh_period,
+                zh_quote: keys.zh_quote,
+                zh_ellipsis: keys.zh_ellipsis,
+                en_ellipsis: keys.en_ellipsis,
+                minor_space: keys.minor_space,
+                preserve_layout: keys.preserve_layout,
+                rules: keys.rules,
+                ..Config::default()
+            },
+        }
+    }
+}
+
+impl Override {
+    fn matches(&self, path: &Path) -> Result<bool, ConfigError> {
+        for pattern in &self.files {
+            let glob = Glob::new(pattern).map_err(|e| ConfigError::Parse {
+                path: None,
+                message: e.to_string(),
+            })?;
+            if glob.compile_matcher().is_match(path) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+fn parse<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    let value = String::deserialize(deserializer)?;
+    value.parse().map(Some).map_err(de::Error::custom)
+}
+
+impl Config {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path).map_err(|e| ConfigError::Io {
+            path: path.to_path_buf(),
+            source: e,
+        })?;
+        content.parse().map_err(|e| match e {
+            ConfigError::Parse { message, .. } => ConfigError::Parse {
+                path: Some(path.to_path_buf()),
+                message,
+            },
+            e => e,
+        })
+    }
+
+    /// the config of the file or directory, merged from the `.chineseword.toml` files of its
+    /// directory and of every parent directory up to the one marked as `root`.
+    ///
+    /// the nearer config file overrides the farther one, and the overrides of a config file are
+    /// applied right after it when their globs match the path relative to the config file.
+    pub fn resolve(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = std::path::absolute(path.as_ref()).map_err(|e| ConfigError::Io {
+            path: path.as_ref().to_path_buf(),
+            source: e,
+        })?;
+        let dir = if path.is_dir() {
+            path.as_path()
+        } else {
+            path.parent().unwrap_or(&path)
+        };
+        let mut found = vec![];
+        for dir in dir.ancestors() {
+            let file = dir.join(CONFIG_FILE_NAME);
+            if !file.is_file() {
+                continue;
+            }
+            let config = Config::load(&file)?;
+            let root = config.root;
+            found.push((dir, file, config));
+            if root {
+                break;
+            }
+        }
+
+        let mut ret = Config::default();
+        for (dir, file, config) in found.into_iter().rev() {
+            let relative = path.strip_prefix(dir).unwrap_or(&path);
+            let config = config.for_file(relative).map_err(|e| match e {
+                ConfigError::Parse { message, .. } => ConfigError::Parse {
+                    path: Some(file.clone()),
+                    message,
+                },
+                e => e,
+            })?;
+            ret.merge(&config);
+        }
+        Ok(ret)
+    }
+
+    /// the config with its overrides applied in order when their globs match the path, which is
+    /// relative to the directory of the config file
+    pub fn for_file(&self, relative: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let mut ret = self.clone();
+        for item in &self.overrides {
+            if item.matches(relative.as_ref())? {
+                ret.merge(&item.config);
+            }
+        }
+        Ok(ret)
+    }
+
+    /// take the keys set in the other config, the rule toggles are added to the current ones
+    pub fn merge(&mut self, other: &Config) {
+        self.locale = other.locale.or(self.locale);
+        self.zh_period = other.zh_period.or(self.zh_period);
+        self.zh_quote = other.zh_quote.or(self.zh_quote);
+        if other.zh_ellipsis.is_some() {
+            self.zh_ellipsis = other.zh_ellipsis.clone();
+        }
+        if other.en_ellipsis.is_some() {
+            self.en_ellipsis = other.en_ellipsis.clone();
+        }
+        self.minor_space = other.minor_space.or(self.minor_space);
+        self.preserve_layout = other.preserve_layout.or(self.preserve_layout);
+        self.rules.extend(other.rules.clone());
+    }
+
+    /// the options set by the config, its overrides are not applied, see [`Config::for_file`]
+    pub fn options(&self) -> NormalizeOptions {
+        let mut options = self.locale.unwrap_or(Locale::ZhHansCn).options();
+        if let Some(period) = self.zh_period {
+            options.zh_period = period;
+        }
+        if let Some(quote) = self.zh_quote {
+            options.zh_quote = quote;
+        }
+        if let Some(ellipsis) = &self.zh_ellipsis {
+            options.zh_ellipsis = ellipsis.clone();
+        }
+        if let Some(ellipsis) = &self.en_ellipsis {
+            options.en_ellipsis = ellipsis.clone();
+        }
+        if let Some(enable) = self.minor_space {
+            options.minor_space = enable;
+        }
+        if let Some(enable) = self.preserve_layout {
+            options.preserve_layout = enable;
+        }
+        options.toggles.extend(self.rules.clone());
+        options
+    }
+}
+
+/// the options for the file or directory, see [`Config::resolve`]
+pub fn resolve_options(path: impl AsRef<Path>) -> Result<NormalizeOptions, ConfigError> {
+    Ok(Config::resolve(path)?.options())
+}
+
+impl FromStr for Config {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        toml::from_str(s).map_err(|e| ConfigError::Parse {
+            path: None,
+            message: e.message().to_string(),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    Parse {
+        path: Option<PathBuf>,
+        message: String,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            ConfigError::Parse {
+                path: Some(path),
+                message,
+            } => write!(f, "{}: {}", path.display(), message),
+            ConfigError::Parse {
+                path: None,
+                message,
+            } => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io { source, .. } => Some(source),
+            ConfigError::Parse { .. } => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{resolve_options, Config, Locale, ZhQuote, CONFIG_FILE_NAME};
+    use std::fs;
+
+    #[test]
+    fn should_apply_locale_then_overrides() {
+        let config = "locale = \"zh-Hant-TW\"\nzh_ellipsis = \"……\"\n"
+            .parse::<Config>()
+            .unwrap();
+        assert_eq!(Some(Locale::ZhHantTw), config.locale);
+        let options = config.options();
+        assert_eq!(ZhQuote::Rect, options.zh_quote);
+        assert_eq!("……", options.zh_ellipsis);
+
+        assert_eq!(
+            Locale::ZhHansCn.options(),
+            "".parse::<Config>().unwrap().options()
+        );
+    }
+
+    #[test]
+    fn should_toggle_rules() {
+        let config = "[rules]\n\"space/minor\" = false\n\"space/minor/zh-letter+digit\" = true\n"
+            .parse::<Config>()
+            .unwrap();
+        let options = config.options();
+        assert!(!options.is_enabled("space/minor/zh-letter+en-letter"));
+        assert!(options.is_enabled("space/minor/zh-letter+digit"));
+        assert!(options.is_enabled("space"));
+    }
+
+    #[test]
+    fn should_reject_unknown_values() {
+        let e = "locale = \"ja-JP\"".parse::<Config>().unwrap_err();
+        assert!(e.to_string().contains("unknown locale `ja-JP`"));
+        assert!("quote = \"rect\"".parse::<Config>().is_err());
+        let e = "[[overrides]]\nfiles = [\"*.md\"]\nzh_perod = \"dot\"\n"
+            .parse::<Config>()
+            .unwrap_err();
+        assert!(e.to_string().contains("zh_perod"));
+        assert!("[[overrides]]\nfiles = [\"*.md\"]\nroot = true\n"
+            .parse::<Config>()
+            .is_err());
+    }
+
+    #[test]
+    fn should_apply_matching_overrides() {
+        let config =
+            "minor_space = false\n\n[[overrides]]\nfiles = [\"en/**\"]\nminor_space = true\n"
+                .parse::<Config>()
+                .unwrap();
+        assert!(!config.options().minor_space);
+        assert!(config.for_file("en/a.md").unwrap().options().minor_space);
+        assert!(!config.for_file("zh/a.md").unwrap().options().minor_space);
+    }
+
+    #[test]
+    fn should_resolve_options_from_nested_config_files() {
+        let root = std::env::temp_dir().join(format!("chineseword-config-{}", std::process::id()));
+        let docs = root.join("docs");
+        fs::create_dir_all(docs.join("en")).unwrap();
+        fs::create_dir_all(docs.join("zh")).unwrap();
+        fs::write(
+            root.join(CONFIG_FILE_NAME),
+            "locale = \"zh-Hant-TW\"\nminor_space = false\n",
+        )
+        .unwrap();
+        fs::write(
+            docs.join(CONFIG_FILE_NAME),
+            "root = true\nminor_space = true\n\n[[overrides]]\nfiles = [\"en/**\"]\n[overrides.rules]\n\"quote\" = false\n",
+        )
+        .unwrap();
+
+        // the nearest config file is marked as root, so the locale of the parent is not used
+        let options = resolve_options(docs.join("zh/a.md")).unwrap();
+        assert_eq!(ZhQuote::Curly, options.zh_quote);
+        assert!(options.minor_space);
+        assert!(options.is_enabled("quote/zh"));
+        let options = resolve_options(docs.join("en/a.md")).unwrap();
+        assert!(!options.is_enabled("quote/zh"));
+
+        fs::write(docs.join(CONFIG_FILE_NAME), "minor_space = true\n").unwrap();
+        let options = resolve_options(docs.join("zh/a.md")).unwrap();
+        assert_eq!(ZhQuote::Rect, options.zh_quote);
+        assert!(options.minor_space);
+        assert!(!resolve_options(root.join("a.md")).unwrap().minor_space);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,140 @@
+use crate::NormalizeOptions;
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+
+static PREFIX: &str = "chineseword-";
+
+/// how a line is handled according to the directives before it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Scope {
+    /// the line is kept as is, directive lines always are
+    Skip,
+    /// the line is normalized without the rules starting with these ids
+    Normalize(Vec<String>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Disable,
+    Enable,
+    DisableNextLine,
+}
+
+/// the directives seen so far in a document, fed line by line.
+///
+/// a directive takes a whole line, as a markdown or html comment like
+/// `<!-- chineseword-disable -->` or behind a `//` or `#` line comment like
+/// `// chineseword-disable-next-line quote`. rule ids or prefixes may follow the directive,
+/// which then only applies to them.
+#[derive(Debug, Default)]
+pub(crate) struct Directives {
+    all: bool,
+    rules: BTreeSet<String>,
+    next_line: Option<Vec<String>>,
+}
+
+impl Directives {
+    pub(crate) fn scope(&mut self, line: &str) -> Scope {
+        if let Some((kind, rules)) = parse(line) {
+            match (kind, rules.is_empty()) {
+                (Kind::Disable, true) => self.all = true,
+                (Kind::Disable, false) => self.rules.extend(rules),
+                (Kind::Enable, true) => {
+                    self.all = false;
+                    self.rules.clear();
+                }
+                (Kind::Enable, false) => {
+                    for rule in rules {
+                        self.rules.remove(&rule);
+                    }
+                }
+                (Kind::DisableNextLine, _) => self.next_line = Some(rules),
+            }
+            return Scope::Skip;
+        }
+        let next_line = self.next_line.take();
+        if self.all || next_line.as_ref().is_some_and(|rules| rules.is_empty()) {
+            return Scope::Skip;
+        }
+        let mut rules = self.rules.iter().cloned().collect::<Vec<_>>();
+        rules.extend(next_line.unwrap_or_default());
+        Scope::Normalize(rules)
+    }
+}
+
+fn parse(line: &str) -> Option<(Kind, Vec<String>)> {
+    let line = line.trim();
+    let body = match line.strip_prefix("<!--") {
+        Some(comment) => comment.strip_suffix("-->")?,
+        None => line.strip_prefix("//").or_else(|| line.strip_prefix('#'))?,
+    };
+    let mut words = body
+        .trim()
+        .strip_prefix(PREFIX)?
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|it| !it.is_empty());
+    let kind = match words.next()? {
+        "disable" => Kind::Disable,
+        "enable" => Kind::Enable,
+        "disable-next-line" => Kind::DisableNextLine,
+        _ => return None,
+    };
+    Some((kind, words.map(|it| it.to_string()).collect()))
+}
+
+/// the options with the rules turned off by the directives
+pub(crate) fn scoped_options<'a>(
+    options: &'a NormalizeOptions,
+    disabled: &[String],
+) -> Cow<'a, NormalizeOptions> {
+    if disabled.is_empty() {
+        return Cow::Borrowed(options);
+    }
+    let mut options = options.clone();
+    for rule in disabled {
+        options.toggles.insert(rule.clone(), false);
+    }
+    Cow::Owned(options)
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "markdown")]
+    use crate::normalize_markdown;
+    use crate::{lint, normalize};
+
+    #[test]
+    fn should_skip_disabled_regions() {
+        assert_eq!(
+            "中文 abc\n<!-- chineseword-disable -->\n中文abc ,\n<!-- chineseword-enable -->\n中文 abc",
+            normalize("中文abc\n<!-- chineseword-disable -->\n中文abc ,\n<!-- chineseword-enable -->\n中文abc")
+        );
+        assert_eq!(
+            "// chineseword-disable-next-line\n中文abc\n中文 abc",
+            normalize("// chineseword-disable-next-line\n中文abc\n中文abc")
+        );
+        assert_eq!(
+            "# chineseword-disable space/minor\n中文abc和中文，中文\n#chineseword-enable space/minor\n中文 abc",
+            normalize("# chineseword-disable space/minor\n中文abc和中文,中文\n#chineseword-enable space/minor\n中文abc")
+        );
+    }
+
+    #[cfg(feature = "markdown")]
+    #[test]
+    fn should_honor_directives_in_markdown() {
+        assert_eq!(
+            "<!-- chineseword-disable-next-line -->\n中文abc\n\n中文 abc\n",
+            normalize_markdown("<!-- chineseword-disable-next-line -->\n中文abc\n\n中文abc\n")
+        );
+    }
+
+    #[test]
+    fn should_honor_directives_in_lint() {
+        let diagnostics = lint("<!-- chineseword-disable-next-line punc -->\n中文abc,中文");
+        assert!(diagnostics
+            .iter()
+            .any(|it| it.rule == "space/minor/zh-letter+en-letter"));
+        assert!(diagnostics.iter().all(|it| !it.rule.starts_with("punc")));
+        assert!(lint("<!-- chineseword-disable -->\n中文abc").is_empty());
+    }
+}
@@ -0,0 +1,359 @@
+use crate::protect::is_placeholder;
+use crate::{is_en_letter, is_zh_letter};
+use std::cmp::min;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::ops::{Add, AddAssign, Range};
+
+static GUESS_LANG_WINDOW: usize = 3;
+
+/// the weight a run of letters needs to stand as a segment on its own. a chinese letter weighs
+/// 2 and an english letter 1, the same ratio `guess_lang` uses.
+static MIN_SEGMENT_WEIGHT: usize = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lang {
+    Zh,
+    En,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LangGuess {
+    pub lang: Lang,
+    /// share of the letters belonging to the guessed language, from 0 to 1
+    pub confidence: f32,
+}
+
+/// a run of text whose punctuations are corrected as the same language
+#[derive(Debug, Clone, PartialEq)]
+pub struct LangSegment {
+    /// byte range of the segment, segments of a text are contiguous and cover all of it
+    pub range: Range<usize>,
+    pub lang: Lang,
+    /// share of the letters belonging to the segment's language, from 0 to 1
+    pub confidence: f32,
+}
+
+/// guess the language of the whole text, the same way a line without clear segments is guessed
+pub fn detect_lang(text: &str) -> LangGuess {
+    let chars = text.chars().collect::<Vec<_>>();
+    let lang = guess_lang(&chars);
+    LangGuess {
+        lang,
+        confidence: Weight::of(&chars).confidence(lang),
+    }
+}
+
+/// split the text into language runs.
+///
+/// characters other than letters belong to the run before them, and short runs surrounded by
+/// the other language are part of the surrounding run.
+pub fn segment_lang(text: &str) -> Vec<LangSegment> {
+    let chars = text.chars().collect::<Vec<_>>();
+    let offsets = text
+        .char_indices()
+        .map(|(idx, _)| idx)
+        .chain(Some(text.len()))
+        .collect::<Vec<_>>();
+    segment_chars(&chars)
+        .into_iter()
+        .map(|segment| LangSegment {
+            range: offsets[segment.range.start]..offsets[segment.range.end],
+            ..segment
+        })
+        .collect()
+}
+
+/// the language of the segment every char belongs to
+pub(crate) fn char_langs(chars: &[char]) -> Vec<Lang> {
+    let mut ret = Vec::with_capacity(chars.len());
+    for segment in segment_chars(chars) {
+        ret.extend(segment.range.map(|_| segment.lang));
+    }
+    ret
+}
+
+/// the languages of a line, segmented once and carried through the passes. the passes only
+/// add, remove or replace the spaces and punctuations around the letters, so following the
+/// letters keeps every char in its segment however the spacing changes.
+pub(crate) struct LineLangs {
+    /// the language of every letter, in order
+    letters: Vec<Lang>,
+    /// the language of the chars before the first letter
+    leading: Lang,
+}
+
+impl LineLangs {
+    pub(crate) fn new(chars: &[char]) -> Self {
+        let langs = char_langs(chars);
+        LineLangs {
+            letters: chars
+                .iter()
+                .zip(&langs)
+                .filter(|(c, _)| letter_lang(c).is_some())
+                .map(|(_, lang)| *lang)
+                .collect(),
+            leading: langs.first().copied().unwrap_or(Lang::Zh),
+        }
+    }
+
+    /// the language of every char of the line as a pass left it, a char other than a letter
+    /// belongs to the letter before it
+    pub(crate) fn of(&self, chars: &[char]) -> Vec<Lang> {
+        let mut letters = self.letters.iter();
+        let mut lang = self.leading;
+        chars
+            .iter()
+            .map(|c| {
+                if letter_lang(c).is_some() {
+                    lang = letters.next().copied().unwrap_or(lang);
+                }
+                lang
+            })
+            .collect()
+    }
+}
+
+/// guess the language from the letters at both ends of the text, the last one left out. only
+/// the letters are looked at, so spacing or punctuating the text never changes the guess.
+pub(crate) fn guess_lang(vec: &[char]) -> Lang {
+    let letters = vec.iter().filter_map(letter_lang).collect::<Vec<_>>();
+    let last = match letters.len() {
+        0 | 1 => return Lang::Zh,
+        len => len - 1,
+    };
+    let head = &letters[..min(GUESS_LANG_WINDOW, last)];
+    let tail = &letters[last.saturating_sub(GUESS_LANG_WINDOW)..last];
+    let mut zh_count = 0;
+    let mut en_count = 0;
+    for lang in head.iter().chain(tail) {
+        match lang {
+            Lang::Zh => zh_count += 1,
+            Lang::En => en_count += 1,
+        }
+    }
+    if zh_count * 2 >= en_count {
+        Lang::Zh
+    } else {
+        Lang::En
+    }
+}
+
+/// the language a letter belongs to, protected spans like urls tell nothing about it
+fn letter_lang(c: &char) -> Option<Lang> {
+    if is_zh_letter(c) {
+        Some(Lang::Zh)
+    } else if is_en_letter(c) && !is_placeholder(c) {
+        Some(Lang::En)
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Weight {
+    zh: usize,
+    en: usize,
+}
+
+impl Weight {
+    fn of(chars: &[char]) -> Self {
+        let mut weight = Weight::default();
+        for lang in chars.iter().filter_map(letter_lang) {
+            weight.push(lang);
+        }
+        weight
+    }
+    fn push(&mut self, lang: Lang) {
+        match lang {
+            Lang::Zh => self.zh += 2,
+            Lang::En => self.en += 1,
+        }
+    }
+    fn get(&self, lang: Lang) -> usize {
+        match lang {
+            Lang::Zh => self.zh,
+            Lang::En => self.en,
+        }
+    }
+    fn confidence(&self, lang: Lang) -> f32 {
+        match self.zh + self.en {
+            0 => 0.0,
+            total => self.get(lang) as f32 / total as f32,
+        }
+    }
+}
+
+impl Add for Weight {
+    type Output = Weight;
+
+    fn add(mut self, rhs: Self) -> Self {
+        self += rhs;
+        self
+    }
+}
+
+impl AddAssign for Weight {
+    fn add_assign(&mut self, rhs: Self) {
+        self.zh += rhs.zh;
+        self.en += rhs.en;
+    }
+}
+
+struct Run {
+    start: usize,
+    lang: Lang,
+    weight: Weight,
+}
+
+impl Run {
+    fn is_short(&self) -> bool {
+        self.weight.get(self.lang) < MIN_SEGMENT_WEIGHT
+    }
+}
+
+/// same as `segment_lang`, in char indexes
+fn segment_chars(chars: &[char]) -> Vec<LangSegment> {
+    let mut runs: Vec<Run> = vec![];
+    for (idx, c) in chars.iter().enumerate() {
+        let Some(lang) = letter_lang(c) else {
+            continue;
+        };
+        match runs.last_mut() {
+            Some(run) if run.lang == lang => run.weight.push(lang),
+            _ => {
+                let mut weight = Weight::default();
+                weight.push(lang);
+                runs.push(Run {
+                    start: idx,
+                    lang,
+                    weight,
+                })
+            }
+        }
+    }
+
+    // a short run surrounded by the other language, like an english word in a chinese
+    // sentence, follows the sentence. runs alternate between the languages, so every run but
+    // the first and the last one is surrounded by the same language.
+    //
+    // the lightest short run is merged first, the runs are linked to each other so a merge
+    // does not move the ones after it.
+    let mut prev = (0..runs.len())
+        .map(|k| k.checked_sub(1))
+        .collect::<Vec<_>>();
+    let mut next = (1..=runs.len())
+        .map(|k| Some(k).filter(|k| *k < runs.len()))
+        .collect::<Vec<_>>();
+    let mut alive = vec![true; runs.len()];
+    let key = |run: &Run, k: usize| Reverse((run.weight.get(run.lang), k));
+    let mut candidates = (1..runs.len().saturating_sub(1))
+        .filter(|k| runs[*k].is_short())
+        .map(|k| key(&runs[k], k))
+        .collect::<BinaryHeap<_>>();
+    while let Some(Reverse((weight, k))) = candidates.pop() {
+        let (Some(before), Some(after)) = (prev[k], next[k]) else {
+            continue;
+        };
+        if !alive[k] || weight != runs[k].weight.get(runs[k].lang) {
+            continue;
+        }
+        let merged = runs[k].weight + runs[after].weight;
+        runs[before].weight += merged;
+        alive[k] = false;
+        alive[after] = false;
+        next[before] = next[after];
+        if let Some(following) = next[after] {
+            prev[following] = Some(before);
+        }
+        if prev[before].is_some() && next[before].is_some() && runs[before].is_short() {
+            candidates.push(key(&runs[before], before));
+        }
+    }
+    let runs = runs
+        .into_iter()
+        .zip(alive)
+        .filter_map(|(run, alive)| alive.then_some(run))
+        .collect::<Vec<_>>();
+
+    if runs.iter().all(Run::is_short) {
+        let lang = guess_lang(chars);
+        return vec![LangSegment {
+            range: 0..chars.len(),
+            lang,
+            confidence: Weight::of(chars).confidence(lang),
+        }];
+    }
+    runs.iter()
+        .enumerate()
+        .map(|(idx, run)| LangSegment {
+            range: if idx == 0 { 0 } else { run.start }
+                ..runs.get(idx + 1).map_or(chars.len(), |next| next.start),
+            lang: run.lang,
+            confidence: run.weight.confidence(run.lang),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{detect_lang, is_normalized, normalize, segment_lang, try_normalize, Lang};
+
+    #[test]
+    fn should_detect_lang_with_confidence() {
+        let guess = detect_lang("中文");
+        assert_eq!(Lang::Zh, guess.lang);
+        assert_eq!(1.0, guess.confidence);
+
+        let guess = detect_lang("中文abcd");
+        assert_eq!(Lang::Zh, guess.lang);
+        assert_eq!(0.5, guess.confidence);
+
+        assert_eq!(0.0, detect_lang("123").confidence);
+    }
+
+    #[test]
+    fn should_split_line_into_segments() {
+        let segments = segment_lang("他说 hello, world. 然后走了。");
+        assert_eq!(
+            vec![(0..7, Lang::Zh), (7..21, Lang::En), (21..36, Lang::Zh)],
+            segments
+                .into_iter()
+                .map(|it| (it.range, it.lang))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn should_keep_short_word_in_surrounding_segment() {
+        let segments = segment_lang("我们使用 rust 语言编写程序");
+        assert_eq!(1, segments.len());
+        assert_eq!(Lang::Zh, segments[0].lang);
+        assert!(segments[0].confidence < 1.0);
+
+        assert_eq!(1, segment_lang("中文eng").len());
+    }
+
+    #[test]
+    fn should_correct_punctuation_by_segment() {
+        assert_eq!(
+            "他说 hello, world. 然后走了。",
+            normalize("他说 hello，world. 然后走了.")
+        );
+        assert_eq!(
+            "今天天气很好，I think so, don't you? 我们出去吧！",
+            normalize("今天天气很好,I think so，don't you？我们出去吧!")
+        );
+    }
+
+    #[test]
+    fn should_settle_when_spacing_moves_the_letters() {
+        // the spaces around `ab` used to turn the line english and back every round
+        assert_eq!(Ok("文 ab……中".to_string()), try_normalize("文ab…中"));
+        assert!(is_normalized("文 ab……中"));
+        assert_eq!(
+            detect_lang("文ab...中cd").lang,
+            detect_lang("文 ab... 中 cd").lang
+        );
+    }
+}
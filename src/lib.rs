@@ -1,11 +1,63 @@
-use itertools::Itertools;
+use itertools::{Either, Itertools};
 use phf::{phf_set, Set};
-use std::cmp::{max, min};
-use std::ops::Add;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::fmt;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::str::FromStr;
+use unicode_script::{Script, UnicodeScript};
 
-static ZH_LEFT_PUNC_SET: Set<char> = phf_set! {'（', '【','《','￥'};
-static ZH_RIGHT_PUNC_SET: Set<char> = phf_set! {'，','。','？','！','：','；','）','】','》'};
-static ZH_MIDDLE_PUNC_SET: Set<char> = phf_set! {'·','～','—','…'};
+#[cfg(feature = "markdown")]
+mod markdown;
+#[cfg(feature = "markdown")]
+pub use markdown::{normalize_markdown, normalize_markdown_with};
+
+mod directive;
+use directive::{scoped_options, Directives, Scope};
+
+mod lang;
+use lang::LineLangs;
+pub use lang::{detect_lang, segment_lang, Lang, LangGuess, LangSegment};
+
+#[cfg(feature = "config")]
+mod config;
+#[cfg(feature = "config")]
+pub use config::{resolve_options, Config, ConfigError, Override, CONFIG_FILE_NAME};
+
+mod locale;
+pub use locale::Locale;
+
+mod rules;
+use rules::Classified;
+pub use rules::{CharClass, RuleSet, SpaceAction, SpaceRule};
+
+mod lint;
+pub use lint::{lint, lint_with, Diagnostic};
+
+mod protect;
+use protect::{is_placeholder, protect};
+
+mod style;
+use style::correct_punc_style;
+
+mod quote;
+use quote::{has_quotes, is_apostrophe, pair_quotes, QuoteContext, QuotePairer};
+
+mod stream;
+pub use stream::{NormalizeLines, Normalizer};
+
+mod offset;
+pub use offset::{normalize_with_map, OffsetMap, OffsetUnit};
+
+/// punctuations of GB/T 15834-2011, the dash `——` and the ellipsis `……` are written with two
+/// of their chars
+static ZH_LEFT_PUNC_SET: Set<char> = phf_set! {'（','【','《','〈','〔','〖','￥'};
+static ZH_RIGHT_PUNC_SET: Set<char> =
+    phf_set! {'，','、','。','？','！','：','；','）','】','》','〉','〕','〗'};
+static ZH_MIDDLE_PUNC_SET: Set<char> = phf_set! {'·','～','〜','—','…','⋯','﹏','\u{3000}'};
 static ZH_QUOTE_SET: Set<char> = phf_set! {'“','‘','「','『','”','’','」','』'};
 
 static EN_LEFT_PUNC_SET: Set<char> = phf_set! {'(','[','{','@','#','$'};
@@ -15,115 +67,260 @@ static EN_MIDDLE_PUNC_SET: Set<char> =
 static EN_RIGHT_PUNC_DIGIT_SET: Set<char> = phf_set! {'?','!',';',')',']','}','%'};
 static EN_QUOTE_SET: Set<char> = phf_set! {'\'','"','`'};
 
-static REMOVE_SPACE_RULE: [(fn(&char) -> bool, fn(&char) -> bool); 19] = [
-    (is_zh_char, is_zh_char),
-    (is_zh_char, digit),
-    (digit, is_zh_char),
-    (is_zh_letter, is_en_letter),
-    (is_en_letter, is_zh_letter),
-    (is_zh_letter, is_en_right_punc),
-    (is_en_left_punc, is_zh_letter),
-    (is_zh_punc, is_en_char),
-    (is_en_char, is_zh_punc),
-    (is_en_letter, is_en_right_punc),
-    (is_en_left_punc, is_en_letter),
-    (is_en_left_punc, is_en_left_punc),
-    (is_en_left_punc, is_en_right_punc),
-    (is_en_left_punc, is_en_middle_punc),
-    (is_en_right_punc, is_en_right_punc),
-    (is_en_middle_punc, is_en_right_punc),
-    (is_en_middle_punc, is_en_middle_punc),
-    (digit, is_en_right_punc),
-    (is_en_left_punc, digit),
-];
-static ADD_SPACE_RULE: [(fn(&char) -> bool, fn(&char) -> bool); 15] = [
-    (is_zh_letter, is_en_left_punc),
-    (is_zh_letter, is_en_middle_punc),
-    (is_en_right_punc, is_zh_letter),
-    (is_en_middle_punc, is_zh_letter),
-    (is_en_letter, is_en_left_punc),
-    (is_en_letter, is_en_middle_punc),
-    (is_en_right_punc, is_en_letter),
-    (is_en_middle_punc, is_en_letter),
-    (is_en_right_punc, is_en_left_punc),
-    (is_en_right_punc, is_en_middle_punc),
-    (is_en_middle_punc, is_en_left_punc),
-    (digit, is_en_left_punc),
-    (digit, is_en_middle_punc),
-    (is_en_right_punc_digit, digit),
-    (is_en_middle_punc, digit),
-];
-
-static MINOR_SPACE_RULE: [(fn(&char) -> bool, fn(&char) -> bool); 4] = [
-    (is_zh_letter, is_en_letter),
-    (is_en_letter, is_zh_letter),
-    (is_zh_letter, digit),
-    (digit, is_zh_letter),
-];
-
-static GUESS_LANG_WINDOW: usize = 3;
-
-#[derive(Debug, PartialEq)]
-enum Lang {
-    Zh,
-    En,
-}
-#[derive(Debug, PartialEq)]
-enum ZhPeriod {
+/// how the chinese full stop should be written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZhPeriod {
+    /// `。`
     Empty,
+    /// `．`
     Dot,
+    /// `. `
     EnDot,
 }
-#[derive(Debug, PartialEq)]
-enum ZhQuote {
+
+/// how chinese quotation marks should be written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZhQuote {
+    /// `“”` and `‘’`
     Curly,
+    /// `「」` and `『』`
     Rect,
+    /// `"` and `'`, spaced from the text outside of them unless it is a chinese punctuation
     Straight,
+    /// ``` `` ``` and `''`, spaced like [`ZhQuote::Straight`]
     Tex,
 }
 
-fn correct_space(mut chars: Vec<char>) -> Vec<char> {
-    let mut i = 0;
-    'outer: while i < chars.len() - 1 {
-        let x = chars[i];
-        if x == ' ' {
-            for (l_rule, r_rule) in REMOVE_SPACE_RULE {
-                if l_rule(&chars[i - 1]) && r_rule(&chars[i + 1]) {
-                    chars[i] = '\u{0}';
-                    continue 'outer;
-                }
+impl FromStr for ZhPeriod {
+    type Err = ParseOptionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "empty" => Ok(ZhPeriod::Empty),
+            "dot" => Ok(ZhPeriod::Dot),
+            "en-dot" => Ok(ZhPeriod::EnDot),
+            _ => Err(ParseOptionError::new("period style", s)),
+        }
+    }
+}
+
+impl FromStr for ZhQuote {
+    type Err = ParseOptionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "curly" => Ok(ZhQuote::Curly),
+            "rect" => Ok(ZhQuote::Rect),
+            "straight" => Ok(ZhQuote::Straight),
+            "tex" => Ok(ZhQuote::Tex),
+            _ => Err(ParseOptionError::new("quote style", s)),
+        }
+    }
+}
+
+/// the error returned when parsing an option value from its name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseOptionError {
+    kind: &'static str,
+    value: String,
+}
+
+impl ParseOptionError {
+    pub(crate) fn new(kind: &'static str, value: &str) -> Self {
+        Self {
+            kind,
+            value: value.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ParseOptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown {} `{}`", self.kind, self.value)
+    }
+}
+
+impl std::error::Error for ParseOptionError {}
+
+/// options used by [`normalize_with`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NormalizeOptions {
+    pub zh_period: ZhPeriod,
+    pub zh_quote: ZhQuote,
+    pub zh_ellipsis: String,
+    pub en_ellipsis: String,
+    pub minor_space: bool,
+    /// keep the line endings, the trailing newline and the indentation of every line
+    pub preserve_layout: bool,
+    /// the spacing rules
+    pub rules: RuleSet,
+    /// rules and passes turned on or off by id prefix, like `space/minor` or `quote`, the
+    /// longest matching prefix wins and everything is on by default
+    pub toggles: BTreeMap<String, bool>,
+}
+
+impl Default for NormalizeOptions {
+    fn default() -> Self {
+        Self {
+            zh_period: ZhPeriod::Empty,
+            zh_quote: ZhQuote::Curly,
+            zh_ellipsis: "……".to_string(),
+            en_ellipsis: "...".to_string(),
+            minor_space: true,
+            preserve_layout: false,
+            rules: RuleSet::default(),
+            toggles: BTreeMap::new(),
+        }
+    }
+}
+
+impl NormalizeOptions {
+    pub fn builder() -> NormalizeOptionsBuilder {
+        NormalizeOptionsBuilder::default()
+    }
+
+    /// whether the rule or pass with the id is turned on, a prefix matches whole segments of the
+    /// id, so `space/minor` covers `space/minor/zh-letter+digit` but not `space/minority`
+    pub fn is_enabled(&self, id: &str) -> bool {
+        self.toggles
+            .iter()
+            .filter(|(prefix, _)| {
+                id.strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .is_none_or(|(_, enabled)| *enabled)
+    }
+
+    /// the spacing rules left once the toggles are applied
+    pub(crate) fn enabled_rules(&self) -> Cow<'_, RuleSet> {
+        if self.toggles.is_empty() {
+            return Cow::Borrowed(&self.rules);
+        }
+        let mut rules = self.rules.clone();
+        rules.retain(|rule| self.is_enabled(rule.id));
+        Cow::Owned(rules)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct NormalizeOptionsBuilder {
+    options: NormalizeOptions,
+}
+
+impl NormalizeOptionsBuilder {
+    /// start from the profile of the locale, the options set before are reset except
+    /// `preserve_layout`
+    pub fn locale(mut self, locale: Locale) -> Self {
+        self.options = NormalizeOptions {
+            preserve_layout: self.options.preserve_layout,
+            ..locale.options()
+        };
+        self
+    }
+    pub fn zh_period(mut self, period: ZhPeriod) -> Self {
+        self.options.zh_period = period;
+        self
+    }
+    pub fn zh_quote(mut self, quote: ZhQuote) -> Self {
+        self.options.zh_quote = quote;
+        self
+    }
+    pub fn zh_ellipsis(mut self, ellipsis: impl Into<String>) -> Self {
+        self.options.zh_ellipsis = ellipsis.into();
+        self
+    }
+    pub fn en_ellipsis(mut self, ellipsis: impl Into<String>) -> Self {
+        self.options.en_ellipsis = ellipsis.into();
+        self
+    }
+    pub fn minor_space(mut self, enable: bool) -> Self {
+        self.options.minor_space = enable;
+        self
+    }
+    pub fn preserve_layout(mut self, enable: bool) -> Self {
+        self.options.preserve_layout = enable;
+        self
+    }
+    pub fn rules(mut self, rules: RuleSet) -> Self {
+        self.options.rules = rules;
+        self
+    }
+    /// turn on the rules and passes whose id starts with the prefix
+    pub fn enable(mut self, prefix: impl Into<String>) -> Self {
+        self.options.toggles.insert(prefix.into(), true);
+        self
+    }
+    /// turn off the rules and passes whose id starts with the prefix
+    pub fn disable(mut self, prefix: impl Into<String>) -> Self {
+        self.options.toggles.insert(prefix.into(), false);
+        self
+    }
+    pub fn build(self) -> NormalizeOptions {
+        self.options
+    }
+}
+
+fn correct_space(chars: Vec<char>, rules: &RuleSet) -> Vec<char> {
+    let Some(&first) = chars.first() else {
+        return chars;
+    };
+    let mut ret = Vec::with_capacity(chars.len() + chars.len() / 8);
+    // a removed space leaves no neighbour, so the chars around it are not compared
+    let mut prev = None;
+    let mut current = Classified::new(first);
+    for (i, &x) in chars.iter().enumerate() {
+        let Some(&next) = chars.get(i + 1) else {
+            ret.push(x);
+            break;
+        };
+        let next = Classified::new(next);
+        if x == ' ' && i > 0 {
+            let removed = prev.is_some_and(|prev| {
+                rules
+                    .find_classified(SpaceAction::Remove, &prev, &next)
+                    .is_some()
+            });
+            if removed {
+                prev = None;
+            } else {
+                ret.push(x);
+                prev = Some(current);
             }
+        } else if rules
+            .find_classified(SpaceAction::Add, &current, &next)
+            .is_some()
+        {
+            ret.push(x);
+            ret.push(' ');
+            prev = Some(Classified::new(' '));
         } else {
-            for (l_rule, r_rule) in ADD_SPACE_RULE {
-                if l_rule(&x) && r_rule(&chars[i + 1]) {
-                    chars.insert(i + 1, ' ');
-                    i += 1;
-                    break;
-                }
-            }
+            ret.push(x);
+            prev = Some(current);
         }
-        i += 1;
+        current = next;
     }
-    chars.into_iter().filter(|it| it != &'\u{0}').collect_vec()
+    ret
 }
 
-fn correct_minor_space(mut chars: Vec<char>) -> Vec<char> {
-    let mut ret = vec![]; // todo remove copy
-    for i in 0..chars.len() - 1 {
-        let x = chars[i];
-        ret.push(x);
-        for (l_rule, r_rule) in MINOR_SPACE_RULE {
-            if l_rule(&x) && r_rule(&chars[i + 1]) {
+fn correct_minor_space(chars: Vec<char>, rules: &RuleSet) -> Vec<char> {
+    let mut ret = Vec::with_capacity(chars.len() + chars.len() / 8);
+    let mut classified = chars.iter().map(|c| Classified::new(*c)).peekable();
+    while let Some(current) = classified.next() {
+        ret.push(current.c);
+        if let Some(next) = classified.peek() {
+            if rules
+                .find_classified(SpaceAction::Minor, &current, next)
+                .is_some()
+            {
                 ret.push(' ');
-                break;
             }
         }
     }
-    ret.push(chars[chars.len() - 1]);
-    ret.into_iter().collect()
+    ret
 }
 
-fn correct_punc_zh(mut chars: Vec<char>) -> Vec<char> {
+fn correct_punc_zh(mut chars: Vec<char>, langs: &LineLangs) -> Vec<char> {
     static END_PUNC_LIST: [(char, char); 6] = [
         ('，', ','),
         ('。', '.'),
@@ -132,10 +329,13 @@ fn correct_punc_zh(mut chars: Vec<char>) -> Vec<char> {
         ('：', ':'),
         ('；', ';'),
     ];
-    static LEFT_BRACKET: Set<char> = phf_set! {'(','（'};
-    static RIGHT_BRACKET: Set<char> = phf_set! {')','）'};
 
+    let langs = langs.of(&chars);
+    let brackets = match_brackets(&chars);
     'outer: for i in 0..chars.len() {
+        if langs[i] != Lang::Zh {
+            continue;
+        }
         for (zh_end_punc, en_end_punc) in END_PUNC_LIST {
             if chars[i] == en_end_punc && detect_forward(is_zh_char, &chars, i) {
                 chars[i] = zh_end_punc;
@@ -148,52 +348,23 @@ fn correct_punc_zh(mut chars: Vec<char>) -> Vec<char> {
             chars[i] = '）';
         }
         if chars[i] == '（' {
-            let mut j = i + 1;
-            let mut bracket_count = 0;
-            let mut ok = false;
-            while j < chars.len() {
-                if RIGHT_BRACKET.contains(&chars[j]) {
-                    if bracket_count == 0 {
-                        ok = true;
-                        break;
-                    } else {
-                        bracket_count -= 1;
-                    }
-                } else if LEFT_BRACKET.contains(&chars[j]) {
-                    bracket_count += 1;
+            if let Some(j) = brackets[i] {
+                if chars[j] == ')' {
+                    chars[j] = '）';
                 }
-                j += 1;
-            }
-            if ok && chars[j] == ')' {
-                chars[j] = '）';
             }
         }
         if chars[i] == '）' {
-            let mut j = i - 1;
-            let mut bracket_count = 0;
-            let mut ok = false;
-            loop {
-                if LEFT_BRACKET.contains(&chars[j]) {
-                    if bracket_count == 0 {
-                        ok = true;
-                        break;
-                    } else {
-                        bracket_count -= 1;
-                    }
-                } else if RIGHT_BRACKET.contains(&chars[j]) {
-                    bracket_count += 1;
+            if let Some(j) = brackets[i] {
+                if chars[j] == '(' {
+                    chars[j] = '（';
                 }
-
-                j -= 1;
-            }
-            if ok && chars[j] == '(' {
-                chars[j] = '（';
             }
         }
     }
     chars
 }
-fn correct_punc_en(mut chars: Vec<char>) -> Vec<char> {
+fn correct_punc_en(mut chars: Vec<char>, langs: &LineLangs) -> Vec<char> {
     static END_PUNC_LIST: [(char, char); 6] = [
         ('，', ','),
         ('。', '.'),
@@ -202,10 +373,13 @@ fn correct_punc_en(mut chars: Vec<char>) -> Vec<char> {
         ('：', ':'),
         ('；', ';'),
     ];
-    static LEFT_BRACKET: Set<char> = phf_set! {'(','（'};
-    static RIGHT_BRACKET: Set<char> = phf_set! {')','）'};
 
+    let langs = langs.of(&chars);
+    let brackets = match_brackets(&chars);
     'outer: for i in 0..chars.len() {
+        if langs[i] != Lang::En {
+            continue;
+        }
         for (zh_end_punc, en_end_punc) in END_PUNC_LIST {
             if chars[i] == zh_end_punc && detect_forward(is_en_char, &chars, i) {
                 chars[i] = en_end_punc;
@@ -218,165 +392,217 @@ fn correct_punc_en(mut chars: Vec<char>) -> Vec<char> {
             chars[i] = ')';
         }
         if chars[i] == '(' {
-            let mut j = i + 1;
-            let mut bracket_count = 0;
-            let mut ok = false;
-            while j < chars.len() {
-                if RIGHT_BRACKET.contains(&chars[j]) {
-                    if bracket_count == 0 {
-                        ok = true;
-                        break;
-                    } else {
-                        bracket_count -= 1;
-                    }
-                } else if LEFT_BRACKET.contains(&chars[j]) {
-                    bracket_count += 1;
+            if let Some(j) = brackets[i] {
+                if chars[j] == '）' {
+                    chars[j] = ')';
                 }
-                j += 1;
-            }
-            if ok && chars[j] == '）' {
-                chars[j] = ')';
             }
         }
         if chars[i] == ')' {
-            let mut j = i - 1;
-            let mut bracket_count = 0;
-            let mut ok = false;
-            loop {
-                if LEFT_BRACKET.contains(&chars[j]) {
-                    if bracket_count == 0 {
-                        ok = true;
-                        break;
-                    } else {
-                        bracket_count -= 1;
-                    }
-                } else if RIGHT_BRACKET.contains(&chars[j]) {
-                    bracket_count += 1;
+            if let Some(j) = brackets[i] {
+                if chars[j] == '（' {
+                    chars[j] = '(';
                 }
-
-                j -= 1;
-            }
-            if ok && chars[j] == '（' {
-                chars[j] = '(';
             }
         }
     }
     chars
 }
 
-fn correct_quote_zh(mut chars: Vec<char>) -> Vec<char> {
+/// the bracket every bracket of the line is paired with, `(` and `（` pair with either `)` or
+/// `）` so a half converted pair still matches
+fn match_brackets(chars: &[char]) -> Vec<Option<usize>> {
+    let mut ret = vec![None; chars.len()];
+    let mut opens = vec![];
+    for (idx, c) in chars.iter().enumerate() {
+        match c {
+            '(' | '（' => opens.push(idx),
+            ')' | '）' => {
+                if let Some(open) = opens.pop() {
+                    ret[open] = Some(idx);
+                    ret[idx] = Some(open);
+                }
+            }
+            _ => {}
+        }
+    }
+    ret
+}
+
+fn correct_quote_zh(chars: Vec<char>, langs: &LineLangs, quotes: QuoteContext) -> Vec<char> {
     static DOUBLE_QUOTE_LIST: Set<char> = phf_set! {'"', '“', '”'};
     static SINGLE_QUOTE_LIST: Set<char> = phf_set! {'‘', '’'};
-    let mut quote_state = 0;
-    let mut quote_state_2 = 0;
-    for i in 0..chars.len() {
-        if DOUBLE_QUOTE_LIST.contains(&chars[i]) {
-            if quote_state == 0 {
-                chars[i] = '“';
-                quote_state = 1;
-            } else {
-                chars[i] = '”';
-                quote_state = 0;
-            }
-            if i > 0 && chars[i - 1] == ' ' {
-                chars[i - 1] = '\u{0}';
-            }
-            if i < chars.len() - 1 && chars[i + 1] == ' ' {
-                chars[i + 1] = '\u{0}';
-            }
-        } else if SINGLE_QUOTE_LIST.contains(&chars[i]) {
-            if quote_state_2 == 0 {
-                chars[i] = '‘';
-                quote_state_2 = 1;
-            } else {
-                chars[i] = '’';
-                quote_state_2 = 0;
-            }
-            if i > 0 && chars[i - 1] == ' ' {
-                chars[i - 1] = '\u{0}';
-            }
-            if i < chars.len() - 1 && chars[i + 1] == ' ' {
-                chars[i + 1] = '\u{0}';
-            }
+    if quotes.unbalanced {
+        return chars;
+    }
+    let langs = langs.of(&chars);
+    let mut ret = Vec::with_capacity(chars.len());
+    // quotes left open by the lines before are closed first
+    let mut quote_state = quotes.double.is_some() as i32;
+    let mut quote_state_2 = quotes.single.is_some() as i32;
+    // a pair of quotes follows the language at its opening quote
+    let mut quote_lang = quotes.double.unwrap_or(Lang::Zh);
+    let mut quote_lang_2 = quotes.single.unwrap_or(Lang::Zh);
+    // whether the single quote is the innermost open one, a single quote left open by the lines
+    // before is taken to be nested in the double one
+    let mut single_inner = quotes.single.is_some();
+    // the space right after a chinese quote is dropped
+    let mut skip_space = false;
+    for (i, &c) in chars.iter().enumerate() {
+        if std::mem::take(&mut skip_space) && c == ' ' {
+            continue;
+        }
+        if c == '’' && is_apostrophe(&chars, i, single_inner) {
+            ret.push(c);
+            continue;
+        }
+        let (state, lang, open, close) = if DOUBLE_QUOTE_LIST.contains(&c) {
+            (&mut quote_state, &mut quote_lang, '“', '”')
+        } else if SINGLE_QUOTE_LIST.contains(&c) {
+            (&mut quote_state_2, &mut quote_lang_2, '‘', '’')
+        } else {
+            ret.push(c);
+            continue;
+        };
+        if *state == 0 {
+            *lang = langs[i];
         }
+        let opened = *state == 0;
+        let quote = if opened { open } else { close };
+        *state = 1 - *state;
+        single_inner = quote_state_2 == 1 && (open == '‘' || !opened);
+        if *lang != Lang::Zh {
+            ret.push(c);
+            continue;
+        }
+        // the space right before is dropped as well
+        if ret.last() == Some(&' ') {
+            ret.pop();
+        }
+        ret.push(quote);
+        skip_space = true;
     }
-    chars.into_iter().filter(|it| it != &'\u{0}').collect()
+    ret
 }
-fn correct_quote_en(mut chars: Vec<char>) -> Vec<char> {
+
+fn correct_quote_en(chars: Vec<char>, langs: &LineLangs, quotes: QuoteContext) -> Vec<char> {
     static DOUBLE_QUOTE_LIST: Set<char> = phf_set! {'"', '“', '”'};
-    let mut quote_state = 0;
-    let mut i = 0;
-    while i < chars.len() {
-        if DOUBLE_QUOTE_LIST.contains(&chars[i]) {
+    let langs = langs.of(&chars);
+    let mut ret = Vec::with_capacity(chars.len() + 4);
+    let mut quote_state = quotes.double.is_some() as i32;
+    let mut quote_state_2 = quotes.single.is_some() as i32;
+    // a pair of quotes follows the language at its opening quote
+    let mut quote_lang = quotes.double.unwrap_or(Lang::En);
+    let mut quote_lang_2 = quotes.single.unwrap_or(Lang::En);
+    let mut single_inner = quotes.single.is_some();
+    // the space right after an opening quote is dropped
+    let mut skip_space = false;
+    for (i, &c) in chars.iter().enumerate() {
+        if std::mem::take(&mut skip_space) && c == ' ' {
+            continue;
+        }
+        let lang = langs[i];
+        if DOUBLE_QUOTE_LIST.contains(&c) && !quotes.unbalanced {
             if quote_state == 0 {
+                quote_lang = lang;
+            }
+            single_inner = quote_state == 1 && quote_state_2 == 1;
+            if quote_lang != Lang::En {
+                quote_state = 1 - quote_state;
+                ret.push(c);
+            } else if quote_state == 0 {
                 quote_state = 1;
-                chars[i] = '"'; // todo tex quote
-                if i > 0 && chars[i - 1] != ' ' {
-                    i += 1;
-                    chars.insert(i - 1, ' ');
-                }
-                if i < chars.len() - 1 && chars[i + 1] == ' ' {
-                    chars[i + 1] = '\u{0}';
+                if i > 0 && ret.last() != Some(&' ') {
+                    ret.push(' ');
                 }
+                ret.push('"'); // todo tex quote
+                skip_space = true;
             } else {
                 quote_state = 0;
-                chars[i] = '"';
-                if i > 0 && chars[i - 1] == ' ' {
-                    chars[i - 1] = '\u{0}';
+                if ret.last() == Some(&' ') {
+                    ret.pop();
                 }
-                if i < chars.len() - 1 && chars[i + 1] != ' ' {
-                    chars.insert(i, ' ');
-                    i += 1;
+                // the space separating the quote from the next word goes before it
+                if chars.get(i + 1).is_some_and(|next| *next != ' ') {
+                    ret.push(' ');
                 }
+                ret.push('"');
+            }
+        } else if c == '’' && is_apostrophe(&chars, i, single_inner) {
+            ret.push(if lang == Lang::En { '\'' } else { c });
+        } else if c == '‘' || c == '’' {
+            if quote_state_2 == 0 {
+                quote_lang_2 = lang;
             }
-        } else if chars[i] == '‘' {
-            chars[i] = '\''; // todo tex quote
-        } else if chars[i] == '’' {
-            chars[i] = '\'';
+            quote_state_2 = 1 - quote_state_2;
+            single_inner = quote_state_2 == 1;
+            // quotes which cannot be paired follow the language around them
+            let pair_lang = if quotes.unbalanced {
+                lang
+            } else {
+                quote_lang_2
+            };
+            ret.push(if pair_lang == Lang::En { '\'' } else { c }); // todo tex quote
+        } else {
+            ret.push(c);
         }
-        i += 1;
     }
-    chars.into_iter().filter(|it| it != &'\u{0}').collect()
+    ret
 }
 
-fn correct_ellipsis(mut chars: Vec<char>, ellipsis: &str) -> Vec<char> {
-    static ELLIPSIS_LIST: Set<char> = phf_set! {'.','。','·','…'};
+fn correct_ellipsis(
+    chars: Vec<char>,
+    langs: &LineLangs,
+    zh_ellipsis: &str,
+    en_ellipsis: &str,
+) -> Vec<char> {
+    static ELLIPSIS_LIST: Set<char> = phf_set! {'.','。','·','…','⋯'};
+    let langs = langs.of(&chars);
+    let mut ellipses = vec![];
     let mut i = 0;
     while i < chars.len() {
         if ELLIPSIS_LIST.contains(&chars[i]) {
-            let mut ellipsis_count = if chars[i] == '…' { 3 } else { 1 };
+            let mut ellipsis_count = if matches!(chars[i], '…' | '⋯') {
+                3
+            } else {
+                1
+            };
             let mut j = i + 1;
             while j < chars.len() {
                 if ELLIPSIS_LIST.contains(&chars[j]) {
-                    ellipsis_count += if chars[j] == '…' { 3 } else { 1 };
+                    ellipsis_count += if matches!(chars[j], '…' | '⋯') {
+                        3
+                    } else {
+                        1
+                    };
                 } else {
                     break;
                 }
                 j += 1;
             }
             if ellipsis_count >= 3 {
-                dbg!(i, j, ellipsis_count);
-                let chars1 = ellipsis.chars().collect_vec();
-                let ellipsis_lens = chars1.len();
-                for (idx, c) in dbg!(chars1).into_iter().enumerate() {
-                    if i + idx >= j {
-                        chars.insert(j, c);
-                    } else if i + idx >= chars.len() {
-                        chars.push(c);
-                    } else {
-                        chars[i + idx] = c;
-                    }
-                }
-                for k in i + ellipsis_lens..j {
-                    chars[k] = '\u{0}';
-                }
-                dbg!(&chars);
+                ellipses.push(i..j);
             }
             i = j;
         }
         i += 1;
     }
-    chars.into_iter().filter(|it| it != &'\u{0}').collect()
+    if ellipses.is_empty() {
+        return chars;
+    }
+    let mut ret = Vec::with_capacity(chars.len());
+    let mut last = 0;
+    for range in ellipses {
+        ret.extend_from_slice(&chars[last..range.start]);
+        let ellipsis = match langs[range.start] {
+            Lang::Zh => zh_ellipsis,
+            Lang::En => en_ellipsis,
+        };
+        ret.extend(ellipsis.chars());
+        last = range.end;
+    }
+    ret.extend_from_slice(&chars[last..]);
+    ret
 }
 
 fn detect_forward(detector: fn(&char) -> bool, slices: &[char], idx: usize) -> bool {
@@ -507,141 +733,659 @@ pub fn convert_full_width_char(letter: &str) -> &str {
     }
 }
 
-fn guess_lang(vec: &Vec<char>) -> Lang {
-    let mut i = 0;
-    let mut j = vec.len() - 1;
-    while i < j && !is_zh_letter(&vec[i]) && !is_en_letter(&vec[i]) {
-        i += 1;
-    }
-    while i < j && !is_zh_letter(&vec[j]) && !is_en_letter(&vec[j]) {
-        j -= 1;
-    }
-    if i >= j {
-        return Lang::Zh;
+fn correct_zh_period(chars: Vec<char>, period: ZhPeriod) -> Vec<char> {
+    let mark = |c: char| match (period, c) {
+        (ZhPeriod::Empty, '．') => Some("。"),
+        (ZhPeriod::Dot, '。') => Some("．"),
+        (ZhPeriod::EnDot, '。' | '．') => Some(". "),
+        _ => None,
+    };
+    replace_chars(chars, mark)
+}
+
+fn correct_zh_quote(chars: Vec<char>, quote: ZhQuote) -> Vec<char> {
+    let mark = |c: char| match (quote, c) {
+        (ZhQuote::Curly, '「') => Some("“"),
+        (ZhQuote::Curly, '」') => Some("”"),
+        (ZhQuote::Curly, '『') => Some("‘"),
+        (ZhQuote::Curly, '』') => Some("’"),
+        (ZhQuote::Rect, '“') => Some("「"),
+        (ZhQuote::Rect, '”') => Some("」"),
+        (ZhQuote::Rect, '‘') => Some("『"),
+        (ZhQuote::Rect, '’') => Some("』"),
+        (ZhQuote::Straight, '“' | '「' | '”' | '」') => Some("\""),
+        (ZhQuote::Straight, '‘' | '『' | '’' | '』') => Some("\'"),
+        (ZhQuote::Tex, '“' | '「') => Some("``"),
+        (ZhQuote::Tex, '”' | '」') => Some("\'\'"),
+        (ZhQuote::Tex, '‘' | '『') => Some("`"),
+        (ZhQuote::Tex, '’' | '』') => Some("\'"),
+        _ => None,
+    };
+    if matches!(quote, ZhQuote::Curly | ZhQuote::Rect) {
+        return replace_chars(chars, mark);
     }
-    let mut zh_count = 0;
-    let mut en_count = 0;
-    for k in i..min(i + GUESS_LANG_WINDOW, j) {
-        if is_zh_letter(&vec[k]) {
-            zh_count += 1;
+    // ascii quotes are spaced from the text outside of them, but not from the edges of the line,
+    // a space, a chinese punctuation or another quote, which the spacing passes would undo
+    let spaced = |c: Option<&char>| {
+        c.is_some_and(|c| !c.is_whitespace() && !is_zh_punc(c) && !is_zh_quote(c))
+    };
+    let mut ret = Vec::with_capacity(chars.len() + 8);
+    for (idx, &c) in chars.iter().enumerate() {
+        let Some(replacement) = mark(c) else {
+            ret.push(c);
+            continue;
+        };
+        let opening = matches!(c, '“' | '「' | '‘' | '『');
+        if opening && spaced(idx.checked_sub(1).map(|idx| &chars[idx])) {
+            ret.push(' ');
         }
-        if is_en_letter(&vec[k]) {
-            en_count += 1;
+        ret.extend(replacement.chars());
+        if !opening && spaced(chars.get(idx + 1)) {
+            ret.push(' ');
         }
     }
-    let i1 = j as isize - GUESS_LANG_WINDOW as isize;
-    for k in max(i1, i as isize) as usize..j {
-        if is_zh_letter(&vec[k]) {
-            zh_count += 1;
+    ret
+}
+
+/// replace the chars `f` gives a replacement for, without copying a line which has none
+fn replace_chars(chars: Vec<char>, f: impl Fn(char) -> Option<&'static str>) -> Vec<char> {
+    let Some(first) = chars.iter().position(|c| f(*c).is_some()) else {
+        return chars;
+    };
+    let mut ret = Vec::with_capacity(chars.len() + 8);
+    ret.extend_from_slice(&chars[..first]);
+    for &c in &chars[first..] {
+        match f(c) {
+            Some(replacement) => ret.extend(replacement.chars()),
+            None => ret.push(c),
         }
-        if is_en_letter(&vec[k]) {
-            en_count += 1;
+    }
+    ret
+}
+
+pub fn normalize(content: impl Into<String>) -> String {
+    normalize_with(content, &NormalizeOptions::default())
+}
+
+/// same as [`normalize`], but the content is borrowed back when it is already normalized
+pub fn normalize_cow(content: &str) -> Cow<'_, str> {
+    normalize_cow_with(content, &NormalizeOptions::default())
+}
+
+/// same as [`normalize_with`], but the content is borrowed back when it is already normalized.
+///
+/// the lines before the first one which changes are not normalized twice.
+pub fn normalize_cow_with<'a>(content: &'a str, options: &NormalizeOptions) -> Cow<'a, str> {
+    if changes_layout(content, options) {
+        return Cow::Owned(normalize_with(content, options));
+    }
+    let lines = split_lines(content, options);
+    let Some((first, normalized)) = first_change(&lines, options) else {
+        return Cow::Borrowed(content);
+    };
+    let mut normalized = Some(normalized);
+    let joined: Result<_, Infallible> = join_lines(
+        content.len(),
+        &lines,
+        options,
+        |idx, line, options, quotes| {
+            Ok(match idx.cmp(&first) {
+                Ordering::Less => line.to_string(),
+                Ordering::Equal => normalized.take().unwrap_or_default(),
+                Ordering::Greater => normalize_line(line, options, quotes),
+            })
+        },
+    );
+    Cow::Owned(joined.unwrap_or_else(|never| match never {}))
+}
+
+/// whether [`normalize`] leaves the content as is
+pub fn is_normalized(content: &str) -> bool {
+    is_normalized_with(content, &NormalizeOptions::default())
+}
+
+/// whether normalizing the content leaves it as is, without building the normalized content.
+///
+/// lines are checked in order and the check stops at the first one which changes. quotes are
+/// paired as the lines come, taking the paragraph to be balanced until its end tells otherwise.
+pub fn is_normalized_with(content: &str, options: &NormalizeOptions) -> bool {
+    let unbalanced = QuoteContext {
+        unbalanced: true,
+        ..QuoteContext::default()
+    };
+    let mut directives = Directives::default();
+    let mut pairer = QuotePairer::default();
+    // whether a line of the paragraph changes only when its quotes are paired
+    let mut balanced_change = false;
+    // the lines with quotes left as they are when paired, to check again when they cannot be
+    let mut pending: Vec<(&str, Cow<NormalizeOptions>)> = vec![];
+    // at the end of a paragraph, whether its lines are left as they are under the quote context
+    // they turn out to have
+    let paragraph_normalized =
+        |pairer: &mut QuotePairer,
+         balanced_change: bool,
+         pending: Vec<(&str, Cow<NormalizeOptions>)>| {
+            if pairer.finish().is_empty() {
+                !balanced_change
+            } else {
+                pending
+                    .into_iter()
+                    .all(|(text, options)| normalize_line(text, &options, unbalanced) == text)
+            }
+        };
+    for (line_no, (_, text, _)) in layouts(content, options).enumerate() {
+        let scope = directives.scope(text);
+        let paired = match scope {
+            Scope::Skip => "",
+            Scope::Normalize(_) => text,
+        };
+        // a blank or skipped line ends the paragraph, see `pair_quotes`
+        let quotes = if paired.trim().is_empty() {
+            let pending = std::mem::take(&mut pending);
+            if !paragraph_normalized(&mut pairer, std::mem::take(&mut balanced_change), pending) {
+                return false;
+            }
+            QuoteContext::default()
+        } else {
+            let quotes = pairer.context();
+            pairer.push(line_no, paired);
+            quotes
+        };
+        let Scope::Normalize(disabled) = &scope else {
+            continue;
+        };
+        let options = scoped_options(options, disabled);
+        // a line without quotes changes the same whether they are paired or not
+        if normalize_line(text, &options, quotes) == text {
+            if has_quotes(text) {
+                pending.push((text, options));
+            }
+        } else if !has_quotes(text) || normalize_line(text, &options, unbalanced) != text {
+            return false;
+        } else {
+            balanced_change = true;
         }
     }
-    if zh_count * 2 >= en_count {
-        Lang::Zh
+    paragraph_normalized(&mut pairer, balanced_change, pending) && !changes_layout(content, options)
+}
+
+pub fn normalize_with(content: impl Into<String>, options: &NormalizeOptions) -> String {
+    let content = content.into();
+    let normalized: Result<_, Infallible> =
+        map_lines(&content, options, |_, line, options, quotes| {
+            Ok(normalize_line(line, options, quotes))
+        });
+    normalized.unwrap_or_else(|never| match never {})
+}
+
+/// a line of the content, with how it is normalized
+pub(crate) struct Line<'a> {
+    pub(crate) indent: &'a str,
+    pub(crate) text: &'a str,
+    pub(crate) ending: &'a str,
+    scope: Scope,
+    quotes: QuoteContext,
+}
+
+impl Line<'_> {
+    /// the normalized text of the line along with its indentation, lines skipped by the ignore
+    /// directives are kept as is
+    pub(crate) fn normalize(&self, options: &NormalizeOptions) -> String {
+        let text = match &self.scope {
+            Scope::Skip => Cow::Borrowed(self.text),
+            Scope::Normalize(disabled) => Cow::Owned(normalize_line(
+                self.text,
+                &scoped_options(options, disabled),
+                self.quotes,
+            )),
+        };
+        format!("{}{}", self.indent, text)
+    }
+}
+
+/// split the content into lines, with the options left by the ignore directives before every
+/// line and the quotes left open by the lines before it. lines skipped by the directives pair
+/// their quotes on their own.
+///
+/// with `preserve_layout` the indentation and the line ending of every line are kept aside,
+/// otherwise the lines are split the way `str::lines` does.
+fn split_lines<'a>(content: &'a str, options: &NormalizeOptions) -> Vec<Line<'a>> {
+    split_lines_with(content, options, &mut Directives::default())
+}
+
+/// same as [`split_lines`], for content following the lines the directives were fed with
+pub(crate) fn split_lines_with<'a>(
+    content: &'a str,
+    options: &NormalizeOptions,
+    directives: &mut Directives,
+) -> Vec<Line<'a>> {
+    let layouts = layouts(content, options).collect::<Vec<_>>();
+    let scopes = layouts
+        .iter()
+        .map(|(_, text, _)| directives.scope(text))
+        .collect::<Vec<_>>();
+    let (contexts, _) =
+        pair_quotes(
+            layouts
+                .iter()
+                .zip(&scopes)
+                .map(|((_, text, _), scope)| match scope {
+                    Scope::Skip => "",
+                    Scope::Normalize(_) => *text,
+                }),
+        );
+    layouts
+        .into_iter()
+        .zip(scopes)
+        .zip(contexts)
+        .map(|(((indent, text, ending), scope), quotes)| Line {
+            indent,
+            text,
+            ending,
+            scope,
+            quotes,
+        })
+        .collect()
+}
+
+/// the indentation, the text and the line ending of every line
+fn layouts<'a>(
+    content: &'a str,
+    options: &NormalizeOptions,
+) -> impl Iterator<Item = (&'a str, &'a str, &'a str)> {
+    if options.preserve_layout {
+        Either::Left(content.split_inclusive('\n').map(split_layout))
     } else {
-        Lang::En
-    }
-}
-
-fn correct_zh_period(content: &str, period: ZhPeriod) -> String {
-    match period {
-        ZhPeriod::Empty => content.replace('．', "。"),
-        ZhPeriod::Dot => content.replace('。', "．"),
-        ZhPeriod::EnDot => content.replace('。', ". ").replace('．', ". "),
-    }
-}
-fn correct_zh_quote(content: &str, quote: ZhQuote) -> String {
-    match quote {
-        ZhQuote::Curly => content
-            .replace('「', "“")
-            .replace('」', "”")
-            .replace('『', "‘")
-            .replace('』', "’"),
-        ZhQuote::Rect => content
-            .replace('“', "「")
-            .replace('”', "」")
-            .replace('‘', "『")
-            .replace('’', "』"),
-        ZhQuote::Straight => content
-            .replace('“', " \"")
-            .replace('”', "\" ")
-            .replace('‘', " \'")
-            .replace('’', "\' ")
-            .replace('「', " \"")
-            .replace('」', "\" ")
-            .replace('『', " \'")
-            .replace('』', "\' "),
-        ZhQuote::Tex => content
-            .replace('“', " ``")
-            .replace('”', "\'\' ")
-            .replace('‘', " `")
-            .replace('’', "\' ")
-            .replace('「', " ``")
-            .replace('」', "\'\' ")
-            .replace('『', " `")
-            .replace('』', "\' "),
+        Either::Right(content.lines().map(|line| ("", line, "")))
     }
 }
 
-pub fn normalize(content: impl Into<String>) -> String {
+/// apply `f` on the content of every line and join them back, lines skipped by the ignore
+/// directives are kept as is. see [`split_lines`].
+///
+/// lines are joined by `\n` without a trailing newline unless `preserve_layout` is set.
+fn map_lines<E>(
+    content: &str,
+    options: &NormalizeOptions,
+    f: impl FnMut(usize, &str, &NormalizeOptions, QuoteContext) -> Result<String, E>,
+) -> Result<String, E> {
+    join_lines(content.len(), &split_lines(content, options), options, f)
+}
+
+fn join_lines<E>(
+    capacity: usize,
+    lines: &[Line],
+    options: &NormalizeOptions,
+    mut f: impl FnMut(usize, &str, &NormalizeOptions, QuoteContext) -> Result<String, E>,
+) -> Result<String, E> {
+    let mut ret = String::with_capacity(capacity);
+    for (idx, line) in lines.iter().enumerate() {
+        if idx > 0 && !options.preserve_layout {
+            ret.push('\n');
+        }
+        ret.push_str(line.indent);
+        match &line.scope {
+            Scope::Skip => ret.push_str(line.text),
+            Scope::Normalize(disabled) => ret.push_str(&f(
+                idx,
+                line.text,
+                &scoped_options(options, disabled),
+                line.quotes,
+            )?),
+        }
+        ret.push_str(line.ending);
+    }
+    Ok(ret)
+}
+
+/// the first line normalizing changes, with its normalized text
+fn first_change(lines: &[Line], options: &NormalizeOptions) -> Option<(usize, String)> {
+    lines.iter().enumerate().find_map(|(idx, line)| {
+        let Scope::Normalize(disabled) = &line.scope else {
+            return None;
+        };
+        let normalized = normalize_line(line.text, &scoped_options(options, disabled), line.quotes);
+        (normalized != line.text).then_some((idx, normalized))
+    })
+}
+
+/// whether joining the lines back changes the content even though no line changes, which is
+/// the case for a trailing newline or `\r\n` line endings unless `preserve_layout` is set
+fn changes_layout(content: &str, options: &NormalizeOptions) -> bool {
+    !options.preserve_layout && (content.ends_with('\n') || content.contains("\r\n"))
+}
+
+/// split a line into its indentation, its content and its line ending
+pub(crate) fn split_layout(line: &str) -> (&str, &str, &str) {
+    let body = line
+        .strip_suffix("\r\n")
+        .or_else(|| line.strip_suffix('\n'))
+        .unwrap_or(line);
+    let text = body.trim_start();
+    (&body[..body.len() - text.len()], text, &line[body.len()..])
+}
+
+/// the error returned by [`try_normalize`] when a line cannot be normalized
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NormalizeError {
+    /// one of the passes panicked on the line, which is a bug worth reporting
+    Panicked { line: usize, message: String },
+    /// the passes kept undoing each other's changes on the line, `normalize` leaves it as the
+    /// last round made it
+    Diverged {
+        line: usize,
+        passes: Vec<&'static str>,
+    },
+}
+
+impl fmt::Display for NormalizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NormalizeError::Panicked { line, message } => {
+                write!(f, "failed to normalize line {}: {}", line + 1, message)
+            }
+            NormalizeError::Diverged { line, passes } => write!(
+                f,
+                "line {} did not settle, {} keep undoing each other",
+                line + 1,
+                passes.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for NormalizeError {}
+
+pub fn try_normalize(content: impl Into<String>) -> Result<String, NormalizeError> {
+    try_normalize_with(content, &NormalizeOptions::default())
+}
+
+/// same as [`normalize_with`], but an input rejected by any pass is reported instead of
+/// unwinding into the caller, and so is a line the passes never agree on.
+pub fn try_normalize_with(
+    content: impl Into<String>,
+    options: &NormalizeOptions,
+) -> Result<String, NormalizeError> {
     let content = content.into();
-    let lines = content.lines();
-    lines
+    map_lines(&content, options, |idx, line, options, quotes| {
+        let processed = catch_unwind(AssertUnwindSafe(|| {
+            process_line(line, options, quotes, None)
+        }))
+        .map_err(|payload| {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|it| it.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            NormalizeError::Panicked { line: idx, message }
+        })?;
+        match processed {
+            Ok(chars) => Ok(chars.into_iter().collect()),
+            Err(Diverged { passes, .. }) => Err(NormalizeError::Diverged { line: idx, passes }),
+        }
+    })
+}
+
+pub(crate) fn normalize_line(
+    line: &str,
+    options: &NormalizeOptions,
+    quotes: QuoteContext,
+) -> String {
+    process_line(line, options, quotes, None)
+        .unwrap_or_else(|diverged| diverged.output)
         .into_iter()
-        .map(|line| {
-            let trimmed = line.trim().split_whitespace().join(" ");
+        .collect()
+}
 
-            let mut trimmed = trimmed
-                .chars()
-                .into_iter()
-                .map(|it| it.to_string())
-                .map(|it| convert_full_width_char(&it).to_string())
-                .flat_map(|it| it.chars().into_iter().collect_vec().into_iter())
-                .collect_vec();
-            dbg!(&trimmed);
-            let lang = guess_lang(&trimmed);
-            loop {
-                let last_edit = trimmed.clone();
-                dbg!(&last_edit);
-                match lang {
-                    Lang::Zh => {
-                        trimmed = correct_space(trimmed);
-                        dbg!(&trimmed);
-                        trimmed = correct_punc_zh(trimmed);
-                        dbg!(&trimmed);
-                        trimmed = correct_quote_zh(trimmed);
-                        dbg!(&trimmed);
-                        trimmed = correct_ellipsis(trimmed, "……");
-                    }
-                    Lang::En => {
-                        trimmed = dbg!(correct_space(trimmed));
-                        dbg!(&trimmed);
-                        trimmed = correct_punc_en(trimmed);
-                        dbg!(&trimmed);
-                        trimmed = correct_quote_en(trimmed);
-                        dbg!(&trimmed);
-                        trimmed = correct_ellipsis(trimmed, "...");
-                        dbg!(&trimmed);
-                    }
-                }
-                if last_edit.eq(&trimmed) {
-                    break;
-                }
+/// the steps `normalize` applies on every line, each one named by a stable rule id
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Pass {
+    Collapse,
+    FullWidth,
+    Space,
+    PuncZh,
+    PuncStyle,
+    PuncEn,
+    QuoteZh,
+    QuoteEn,
+    Ellipsis,
+    MinorSpace,
+    ZhPeriod,
+    ZhQuote,
+}
+
+impl Pass {
+    pub(crate) fn id(&self) -> &'static str {
+        match self {
+            Pass::Collapse => "space/collapse",
+            Pass::FullWidth => "full-width",
+            Pass::Space => "space",
+            Pass::PuncZh => "punc/zh",
+            Pass::PuncStyle => "punc/style",
+            Pass::PuncEn => "punc/en",
+            Pass::QuoteZh => "quote/zh",
+            Pass::QuoteEn => "quote/en",
+            Pass::Ellipsis => "ellipsis",
+            Pass::MinorSpace => "space/minor",
+            Pass::ZhPeriod => "punc/zh-period",
+            Pass::ZhQuote => "quote/zh-style",
+        }
+    }
+
+    /// passes made of rules are turned on or off rule by rule, so a rule can be enabled even
+    /// though the rest of the pass is not
+    fn has_rules(&self) -> bool {
+        matches!(self, Pass::Space | Pass::MinorSpace | Pass::PuncStyle)
+    }
+}
+
+/// called with the pass and the line before and after it, only when the pass changed the line
+pub(crate) type PassObserver<'a> = Option<&'a mut dyn FnMut(Pass, &[char], &[char])>;
+
+fn apply_pass(
+    pass: Pass,
+    options: &NormalizeOptions,
+    chars: Vec<char>,
+    observer: &mut PassObserver,
+    f: impl FnOnce(Vec<char>) -> Vec<char>,
+) -> Vec<char> {
+    if !pass.has_rules() && !options.is_enabled(pass.id()) {
+        return chars;
+    }
+    #[cfg(feature = "tracing")]
+    let _span = tracing::trace_span!("pass", id = pass.id()).entered();
+    let after = match observer {
+        Some(observe) => {
+            let before = chars.clone();
+            let after = f(chars);
+            if before != after {
+                observe(pass, &before, &after);
             }
+            after
+        }
+        None => f(chars),
+    };
+    #[cfg(feature = "tracing")]
+    tracing::trace!(output = %after.iter().collect::<String>());
+    after
+}
 
-            trimmed = correct_minor_space(trimmed);
-            let s = trimmed.into_iter().join("");
-            let s = correct_zh_period(&s, ZhPeriod::Empty);
-            let s = correct_zh_quote(&s, ZhQuote::Curly);
-            s
+/// a line whose passes kept undoing each other, so it never settled
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Diverged {
+    /// ids of the passes which still changed the line
+    pub(crate) passes: Vec<&'static str>,
+    /// the line as the last round of the passes left it
+    pub(crate) output: Vec<char>,
+}
+
+pub(crate) fn process_line(
+    line: &str,
+    options: &NormalizeOptions,
+    quotes: QuoteContext,
+    mut observer: PassObserver,
+) -> Result<Vec<char>, Diverged> {
+    #[cfg(feature = "tracing")]
+    let _span = tracing::debug_span!("normalize_line", line).entered();
+    let trimmed = line.chars().collect_vec();
+    let trimmed = apply_pass(Pass::Collapse, options, trimmed, &mut observer, |chars| {
+        // the ideographic space is a chinese punctuation rather than a separator
+        let is_separator = |c: &char| c.is_whitespace() && *c != '\u{3000}';
+        let mut ret = Vec::with_capacity(chars.len());
+        for c in chars {
+            if !is_separator(&c) {
+                ret.push(c);
+            } else if ret.last().is_some_and(|last| !is_separator(last)) {
+                ret.push(' ');
+            }
+        }
+        if ret.last() == Some(&' ') {
+            ret.pop();
+        }
+        ret
+    });
+    let trimmed = apply_pass(Pass::FullWidth, options, trimmed, &mut observer, |chars| {
+        let mut ret = Vec::with_capacity(chars.len());
+        let mut buf = [0; 4];
+        for c in chars {
+            if c.is_ascii() {
+                ret.push(c);
+            } else {
+                ret.extend(convert_full_width_char(c.encode_utf8(&mut buf)).chars());
+            }
+        }
+        ret
+    });
+    let (trimmed, protected) = protect(trimmed);
+    let rules = options.enabled_rules();
+    // the languages are told once, spacing the line must not move a char to another language
+    let langs = LineLangs::new(&trimmed);
+    let mut iteration = 0;
+    let (mut trimmed, unsettled) = fix_point(trimmed, |chars| {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!("iteration", iteration).entered();
+        iteration += 1;
+        correct_iteration(chars, options, &rules, &langs, quotes, &mut observer)
+    });
+    let diverged = (unsettled > 0).then(|| {
+        unsettled_passes(&trimmed, unsettled, |chars, observer| {
+            correct_iteration(chars, options, &rules, &langs, quotes, observer)
         })
-        .join("\n")
+    });
+    #[cfg(feature = "tracing")]
+    if let Some(passes) = &diverged {
+        tracing::warn!(?passes, "line did not settle");
+    }
+
+    if options.minor_space {
+        trimmed = apply_pass(Pass::MinorSpace, options, trimmed, &mut observer, |chars| {
+            correct_minor_space(chars, &rules)
+        });
+    }
+    let trimmed = apply_pass(Pass::ZhPeriod, options, trimmed, &mut observer, |chars| {
+        correct_zh_period(chars, options.zh_period)
+    });
+    let trimmed = apply_pass(Pass::ZhQuote, options, trimmed, &mut observer, |chars| {
+        correct_zh_quote(chars, options.zh_quote)
+    });
+    let output = protected.restore(trimmed);
+    match diverged {
+        Some(passes) => Err(Diverged { passes, output }),
+        None => Ok(output),
+    }
+}
+
+/// one round of the passes repeated until the line settles
+fn correct_iteration(
+    chars: Vec<char>,
+    options: &NormalizeOptions,
+    rules: &RuleSet,
+    langs: &LineLangs,
+    quotes: QuoteContext,
+    observer: &mut PassObserver,
+) -> Vec<char> {
+    let chars = apply_pass(Pass::Space, options, chars, observer, |chars| {
+        correct_space(chars, rules)
+    });
+    let chars = apply_pass(Pass::PuncZh, options, chars, observer, |chars| {
+        correct_punc_zh(chars, langs)
+    });
+    let chars = apply_pass(Pass::PuncStyle, options, chars, observer, |chars| {
+        correct_punc_style(chars, options)
+    });
+    let chars = apply_pass(Pass::PuncEn, options, chars, observer, |chars| {
+        correct_punc_en(chars, langs)
+    });
+    let chars = apply_pass(Pass::QuoteZh, options, chars, observer, |chars| {
+        correct_quote_zh(chars, langs, quotes)
+    });
+    let chars = apply_pass(Pass::QuoteEn, options, chars, observer, |chars| {
+        correct_quote_en(chars, langs, quotes)
+    });
+    apply_pass(Pass::Ellipsis, options, chars, observer, |chars| {
+        correct_ellipsis(chars, langs, &options.zh_ellipsis, &options.en_ellipsis)
+    })
 }
 
+/// the most rounds of the passes a line goes through before it is given up as not settling
+pub(crate) const MAX_ITERATIONS: usize = 16;
+
+/// repeat `step` until the line stops changing, at most [`MAX_ITERATIONS`] times.
+///
+/// a line which does not settle is left as the last round made it, which is the same for the
+/// same input. the number of rounds it cycles through from there is returned as well, or 1 when
+/// it never repeated, and 0 when it settled.
+///
+/// only the previous state is kept as a whole, the earlier ones are compared by their hash.
+fn fix_point(
+    mut chars: Vec<char>,
+    mut step: impl FnMut(Vec<char>) -> Vec<char>,
+) -> (Vec<char>, usize) {
+    let hash = |chars: &[char]| {
+        let mut hasher = DefaultHasher::new();
+        chars.hash(&mut hasher);
+        hasher.finish()
+    };
+    let mut last = vec![];
+    let mut history = vec![];
+    while history.len() < MAX_ITERATIONS {
+        last.clone_from(&chars);
+        history.push(hash(&chars));
+        chars = step(chars);
+        if last == chars {
+            return (chars, 0);
+        }
+        let current = hash(&chars);
+        if let Some(start) = history.iter().position(|it| *it == current) {
+            return (chars, history.len() - start);
+        }
+    }
+    (chars, 1)
+}
+
+/// replay the `unsettled` rounds a line cycles through to tell which passes keep undoing each
+/// other, in the order they first changed the line
+fn unsettled_passes(
+    chars: &[char],
+    unsettled: usize,
+    mut round: impl FnMut(Vec<char>, &mut PassObserver) -> Vec<char>,
+) -> Vec<&'static str> {
+    let mut passes = vec![];
+    let mut record = |pass: Pass, _: &[char], _: &[char]| {
+        if !passes.contains(&pass) {
+            passes.push(pass);
+        }
+    };
+    let mut chars = chars.to_vec();
+    for _ in 0..unsettled {
+        chars = round(chars, &mut Some(&mut record));
+    }
+    passes.iter().map(Pass::id).collect()
+}
+
+/// every han ideograph, including the extensions, the compatibility ideographs and `〇`
 fn is_zh_letter(letter: &char) -> bool {
-    letter >= &'\u{4e00}' && letter <= &'\u{9fa5}'
+    ('\u{4e00}'..='\u{9fff}').contains(letter) || letter.script() == Script::Han
 }
 
 fn is_zh_left_punc(letter: &char) -> bool {
@@ -665,8 +1409,9 @@ fn is_zh_char(letter: &char) -> bool {
     is_zh_letter(letter) || is_zh_punc(letter) || is_zh_quote(letter)
 }
 
+/// protected spans like urls count as english words, so they are spaced like one
 fn is_en_letter(letter: &char) -> bool {
-    letter.is_ascii_alphabetic()
+    letter.is_ascii_alphabetic() || is_placeholder(letter)
 }
 
 fn is_en_left_punc(letter: &char) -> bool {
@@ -690,22 +1435,20 @@ fn is_en_punc(letter: &char) -> bool {
 fn is_en_char(letter: &char) -> bool {
     is_en_letter(letter) || is_en_punc(letter) || is_en_quote(letter)
 }
-fn letter(letter: &char) -> bool {
-    is_zh_letter(letter) || is_en_letter(letter)
-}
-
-fn punc(letter: &char) -> bool {
-    is_zh_punc(letter) || is_en_punc(letter)
-}
-
 fn digit(letter: &char) -> bool {
     letter.is_ascii_digit()
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{guess_lang, normalize, Lang};
+    use crate::{
+        apply_pass, fix_point, is_normalized, is_normalized_with, is_zh_letter, lang::guess_lang,
+        normalize, normalize_cow, normalize_with, try_normalize, unsettled_passes, Lang,
+        NormalizeError, NormalizeOptions, Pass, PassObserver, ZhPeriod, ZhQuote, MAX_ITERATIONS,
+    };
     use itertools::Itertools;
+    use proptest::prelude::*;
+    use std::borrow::Cow;
 
     #[test]
     fn should_keep_the_same_given_only_english_word() {
@@ -772,10 +1515,274 @@ mod tests {
     #[test]
     fn should_correct_zh_quote() {
         assert_eq!("你好“世界”", normalize("你好「世界」"));
+        assert_eq!("他说“你好”", normalize("他说 \"你好\""));
+        // a literal nul is kept
+        assert_eq!("他\u{0}说“你好”", normalize("他\u{0}说\"你好\""));
+    }
+
+    #[test]
+    fn should_borrow_normalized_content() {
+        let clean = "中文 abc，中文\n“你好”";
+        assert!(matches!(normalize_cow(clean), Cow::Borrowed(it) if it == clean));
+        assert!(is_normalized(clean));
+
+        let dirty = "中文 abc，中文\n中文abc\n“你好”";
+        assert_eq!(normalize(dirty), normalize_cow(dirty));
+        assert!(matches!(normalize_cow(dirty), Cow::Owned(_)));
+        assert!(!is_normalized(dirty));
+
+        // the quotes of the first line are paired only when the paragraph ends balanced
+        assert!(is_normalized("他说\"你好\"\n再见\""));
+        assert!(!is_normalized("他说\"你好\"\n再见"));
+
+        // the trailing newline is dropped unless the layout is kept
+        assert!(!is_normalized("中文\n"));
+        assert_eq!("中文", normalize_cow("中文\n"));
+        let options = NormalizeOptions::builder().preserve_layout(true).build();
+        assert!(is_normalized_with("  中文\r\n", &options));
     }
 
     #[test]
     fn should_work_on_multiple_line() {
-        assert_eq!("你好“世界”\nEnglish...", normalize("你好「世界」\nEnglish……"));
+        assert_eq!(
+            "你好“世界”\nEnglish...",
+            normalize("你好「世界」\nEnglish……")
+        );
+    }
+
+    #[test]
+    fn should_use_default_options_in_normalize() {
+        let content = "你好「世界」\nEnglish……";
+        assert_eq!(
+            normalize(content),
+            normalize_with(content, &NormalizeOptions::default())
+        );
+    }
+
+    #[test]
+    fn should_respect_normalize_options() {
+        let rect = NormalizeOptions::builder().zh_quote(ZhQuote::Rect).build();
+        assert_eq!("你好「世界」", normalize_with("你好「世界」", &rect));
+        assert_eq!(
+            "他们说：「你好啊」",
+            normalize_with("他们说:\"你好啊\"", &rect)
+        );
+
+        let dot = NormalizeOptions::builder().zh_period(ZhPeriod::Dot).build();
+        assert_eq!("中文．中文", normalize_with("中文.中文", &dot));
+
+        let ellipsis = NormalizeOptions::builder()
+            .zh_ellipsis("…")
+            .en_ellipsis("…")
+            .build();
+        assert_eq!("中文…", normalize_with("中文......", &ellipsis));
+        assert_eq!("English…", normalize_with("English...", &ellipsis));
+
+        let no_minor = NormalizeOptions::builder().minor_space(false).build();
+        assert_eq!("中文abc", normalize_with("中文abc", &no_minor));
+    }
+
+    #[test]
+    fn should_round_trip_ascii_zh_quotes() {
+        for (quote, expected) in [
+            (
+                ZhQuote::Straight,
+                ["他说：\"你好\"。", "\"你好\"", "他说 \"你好\" 吧，'好'"],
+            ),
+            (
+                ZhQuote::Tex,
+                ["他说：``你好''。", "``你好''", "他说 ``你好'' 吧，`好'"],
+            ),
+        ] {
+            let options = NormalizeOptions::builder().zh_quote(quote).build();
+            for (content, expected) in ["他说：“你好”。", "“你好”", "他说“你好”吧，‘好’"]
+                .into_iter()
+                .zip(expected)
+            {
+                let normalized = normalize_with(content, &options);
+                assert_eq!(expected, normalized);
+                assert_eq!(normalized, normalize_with(normalized.as_str(), &options));
+            }
+        }
+    }
+
+    #[test]
+    fn should_not_panic_given_edge_input() {
+        assert_eq!("", normalize(""));
+        assert_eq!("中文\n\nabc", normalize("中文\n\nabc"));
+        assert_eq!("中文", normalize("   中文"));
+        assert_eq!("）", normalize("）"));
+        assert_eq!("中文)", normalize("中文)"));
+        assert_eq!("abc)", normalize("abc)"));
+        assert_eq!(Ok("中文（中文".to_string()), try_normalize("中文(中文"));
+        assert_eq!(Lang::Zh, guess_lang(&[]));
+    }
+
+    #[test]
+    fn should_give_up_on_lines_which_never_settle() {
+        let flip = |mut chars: Vec<char>| {
+            chars[0] = if chars[0] == 'a' { 'b' } else { 'a' };
+            chars
+        };
+        assert_eq!((vec!['a', 'c'], 2), fix_point(vec!['a', 'c'], flip));
+        let (chars, unsettled) = fix_point(vec!['0'], |mut chars| {
+            chars.push('0');
+            chars
+        });
+        assert_eq!((MAX_ITERATIONS + 1, 1), (chars.len(), unsettled));
+        assert_eq!((vec!['a'], 0), fix_point(vec!['a'], |chars| chars));
+
+        // two passes flipping a char each and one changing nothing
+        let options = NormalizeOptions::default();
+        let round = |chars: Vec<char>, observer: &mut PassObserver| {
+            let chars = apply_pass(Pass::Space, &options, chars, observer, flip);
+            let chars = apply_pass(Pass::Ellipsis, &options, chars, observer, |chars| chars);
+            apply_pass(Pass::QuoteEn, &options, chars, observer, |mut chars| {
+                chars[1] = if chars[1] == 'a' { 'b' } else { 'a' };
+                chars
+            })
+        };
+        let (chars, unsettled) = fix_point(vec!['a', 'a'], |chars| round(chars, &mut None));
+        assert_eq!(2, unsettled);
+        assert_eq!(
+            vec!["space", "quote/en"],
+            unsettled_passes(&chars, unsettled, round)
+        );
+
+        let e = NormalizeError::Diverged {
+            line: 0,
+            passes: vec!["space", "quote/en"],
+        };
+        assert_eq!(
+            "line 1 did not settle, space, quote/en keep undoing each other",
+            e.to_string()
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn should_never_panic_given_arbitrary_unicode(content in any::<String>()) {
+            normalize(content.as_str());
+            crate::lint(&content);
+            // a line which does not settle is still normalized, only a panic is a bug
+            let panicked = matches!(
+                try_normalize(content),
+                Err(NormalizeError::Panicked { .. })
+            );
+            prop_assert!(!panicked);
+        }
+
+        #[test]
+        fn should_never_panic_given_punctuation_heavy_input(
+            content in "[中文ab1 ,.:;!?()（）\\[\\]\"“”‘’'「」『』…。，·\n]{0,40}"
+        ) {
+            normalize(content.as_str());
+            let panicked = matches!(
+                try_normalize(content),
+                Err(NormalizeError::Panicked { .. })
+            );
+            prop_assert!(!panicked);
+        }
+
+        #[test]
+        fn should_borrow_only_what_normalize_keeps(
+            content in "[中文ab1 ,.:;!?()（）\"“”‘’…。，\n]{0,40}"
+        ) {
+            let normalized = normalize(content.as_str());
+            prop_assert_eq!(&normalized, &normalize_cow(&content));
+            prop_assert_eq!(normalized == content, is_normalized(&content));
+        }
+    }
+
+    #[test]
+    fn should_treat_every_han_ideograph_as_chinese_letter() {
+        for letter in [
+            '中',
+            '\u{9fa6}',
+            '\u{9fff}',
+            '㐀',
+            '\u{20000}',
+            '\u{2a700}',
+            '\u{2b740}',
+            '\u{2b820}',
+            '\u{2ceb0}',
+            '\u{30000}',
+            '\u{31350}',
+            '\u{2ebf0}',
+            '豈',
+            '\u{2f800}',
+            '〇',
+        ] {
+            assert!(is_zh_letter(&letter), "{:?}", letter);
+        }
+        assert!(!is_zh_letter(&'a'));
+        assert!(!is_zh_letter(&'。'));
+        assert!(!is_zh_letter(&'あ'));
+        assert_eq!("𠀀 abc", normalize("𠀀abc"));
+        assert_eq!("〇，〇", normalize("〇,〇"));
+    }
+
+    #[test]
+    fn should_preserve_layout() {
+        let options = NormalizeOptions::builder().preserve_layout(true).build();
+        assert_eq!(
+            "中文 abc\r\n  - 中文，中文\r\n\n\t中文 abc\n",
+            normalize_with("中文abc  \r\n  - 中文,中文\r\n\n\t中文abc\n", &options)
+        );
+        assert_eq!("  中文 abc", normalize_with("  中文abc", &options));
+        assert_eq!("中文 abc", normalize("  中文abc\n"));
+    }
+
+    #[test]
+    fn should_treat_gb_15834_punctuations_as_chinese() {
+        // enumeration comma
+        assert_eq!("使用 Rust、Go 和 C", normalize("使用 Rust 、 Go 和 C"));
+        assert_eq!("苹果、香蕉，梨", normalize("苹果 、香蕉,梨"));
+        // angle, tortoise shell and lenticular brackets
+        assert_eq!("见〈标题〉，然后", normalize("见 〈 标题 〉,然后"));
+        assert_eq!("〔abc〕中文", normalize("〔 abc 〕 中文"));
+        assert_eq!("〖标题〗，中文", normalize("〖标题〗 ,中文"));
+        // wavy low line, wave dash and the dash
+        assert_eq!("abc﹏def", normalize("abc ﹏ def"));
+        assert_eq!("9:00〜10:00", normalize("9:00 〜 10:00"));
+        assert_eq!("他说——abc", normalize("他说 —— abc"));
+        // ideographic space
+        assert_eq!("第一章\u{3000}abc", normalize("第一章 \u{3000} abc"));
+        assert_eq!(
+            "\u{3000}\u{3000}正文，abc",
+            normalize("\u{3000}\u{3000}正文,abc")
+        );
+    }
+
+    #[test]
+    fn should_toggle_rules_by_id() {
+        let options = NormalizeOptions::builder().disable("space/minor").build();
+        assert_eq!(
+            "中文abc和中文123",
+            normalize_with("中文 abc和中文123", &options)
+        );
+
+        let options = NormalizeOptions::builder()
+            .disable("space/minor")
+            .enable("space/minor/zh-letter+digit")
+            .build();
+        assert_eq!(
+            "中文abc中文 123",
+            normalize_with("中文abc 中文123", &options)
+        );
+
+        let options = NormalizeOptions::builder().disable("quote").build();
+        assert_eq!(
+            "他说：\"你好\"……",
+            normalize_with("他说:\"你好\"...", &options)
+        );
+
+        let options = NormalizeOptions::builder()
+            .disable("space/remove/zh-char+zh-char")
+            .build();
+        assert_eq!("中文 中文", normalize_with("中文 中文", &options));
+        assert!(!options.is_enabled("space/remove/zh-char+zh-char"));
+        assert!(options.is_enabled("space/remove/zh-char+digit"));
+        assert!(options.is_enabled("space/remove/zh-char"));
     }
 }
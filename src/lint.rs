@@ -0,0 +1,375 @@
+use crate::directive::{scoped_options, Directives, Scope};
+use crate::quote::{pair_quotes, QuoteContext, UNBALANCED};
+use crate::style::{check_punc_style, style_rule};
+use crate::{process_line, split_layout, NormalizeOptions, Pass, RuleSet, SpaceAction};
+use similar::{capture_diff_slices, Algorithm, DiffTag};
+use std::ops::Range;
+
+/// a single change `normalize` would make
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// stable id of the rule or pass which made the change, e.g. `space/add/en-letter+en-left-punc`
+    pub rule: &'static str,
+    /// zero based line number
+    pub line: usize,
+    /// byte range inside the line
+    pub columns: Range<usize>,
+    /// byte range inside the whole content
+    pub offset: Range<usize>,
+    pub original: String,
+    pub replacement: String,
+    /// whether `normalize` applies the replacement, the others are only suggestions
+    pub fixed: bool,
+}
+
+pub fn lint(content: &str) -> Vec<Diagnostic> {
+    lint_with(content, &NormalizeOptions::default())
+}
+
+/// report every change [`normalize_with`](crate::normalize_with) would make to the content.
+///
+/// applying the replacements of all fixed diagnostics gives each line the same content as
+/// normalizing it, line endings are not reported, and neither is the indentation when
+/// `preserve_layout` is set. punctuation style violations `normalize` cannot safely fix are
+/// reported as well, with `fixed` unset, and so are the quotes which cannot be paired inside
+/// their paragraph. lines skipped by ignore directives are not reported.
+pub fn lint_with(content: &str, options: &NormalizeOptions) -> Vec<Diagnostic> {
+    let mut directives = Directives::default();
+    let lines = content
+        .split_inclusive('\n')
+        .map(|raw| {
+            let (indent, text, _) = split_layout(raw);
+            // the indentation is only part of what gets normalized when the layout is not kept
+            let (skipped, line) = if options.preserve_layout {
+                (indent.len(), text)
+            } else {
+                (0, &raw[..indent.len() + text.len()])
+            };
+            (raw, skipped, line, directives.scope(text))
+        })
+        .collect::<Vec<_>>();
+    let (contexts, unbalanced) = pair_quotes(lines.iter().map(|(_, _, line, scope)| match scope {
+        Scope::Skip => "",
+        Scope::Normalize(_) => *line,
+    }));
+
+    let mut ret = vec![];
+    let mut line_start = 0;
+    for (line_no, (raw, skipped, line, scope)) in lines.into_iter().enumerate() {
+        let options = match scope {
+            Scope::Skip => {
+                line_start += raw.len();
+                continue;
+            }
+            Scope::Normalize(disabled) => scoped_options(options, &disabled),
+        };
+        let unbalanced = unbalanced
+            .iter()
+            .filter(|it| it.line == line_no)
+            .map(|it| it.idx)
+            .collect::<Vec<_>>();
+        for (columns, original, replacement, rule, fixed) in
+            lint_line(line, &options, contexts[line_no], &unbalanced)
+        {
+            let columns = skipped + columns.start..skipped + columns.end;
+            ret.push(Diagnostic {
+                rule,
+                line: line_no,
+                offset: line_start + columns.start..line_start + columns.end,
+                columns,
+                original,
+                replacement,
+                fixed,
+            });
+        }
+        line_start += raw.len();
+    }
+    ret
+}
+
+/// a change made by one pass, in char indexes of the original line
+pub(crate) struct Edit {
+    span: Range<usize>,
+    rule: &'static str,
+}
+
+type LineDiagnostic = (Range<usize>, String, String, &'static str, bool);
+
+/// the diagnostics of a line, `unbalanced` holds the char indexes of its quotes which cannot be
+/// paired
+fn lint_line(
+    line: &str,
+    options: &NormalizeOptions,
+    quotes: QuoteContext,
+    unbalanced: &[usize],
+) -> Vec<LineDiagnostic> {
+    let original = line.chars().collect::<Vec<_>>();
+    let Trace {
+        normalized,
+        origins,
+        edits,
+        ..
+    } = trace_line(line, options, quotes);
+
+    let offsets = line
+        .char_indices()
+        .map(|(idx, _)| idx)
+        .chain(Some(line.len()))
+        .collect::<Vec<_>>();
+    let mut ret = vec![];
+    let mut expected = 0;
+    let mut inserted = String::new();
+    for (c, origin) in normalized
+        .iter()
+        .zip(&origins)
+        .chain(Some((&'\u{0}', &Some(original.len()))))
+    {
+        match origin {
+            Some(idx) => {
+                let replaced = original[expected..*idx].iter().collect::<String>();
+                if replaced != inserted {
+                    let rule = find_rule(&edits, expected..*idx);
+                    ret.push((
+                        offsets[expected]..offsets[*idx],
+                        replaced,
+                        inserted.clone(),
+                        rule,
+                        true,
+                    ));
+                }
+                inserted.clear();
+                expected = idx + 1;
+            }
+            None => inserted.push(*c),
+        }
+    }
+
+    // violations left in the normalized line, reported where they come from
+    for (range, rule, replacement) in check_punc_style(&normalized) {
+        if !options.is_enabled(rule) {
+            continue;
+        }
+        let Some(start) = origins[range.start] else {
+            continue;
+        };
+        let Some(end) = origins[range.end - 1].map(|it| it + 1) else {
+            continue;
+        };
+        let columns = offsets[start]..offsets[end];
+        ret.push((
+            columns.clone(),
+            line[columns].to_string(),
+            replacement,
+            rule,
+            false,
+        ));
+    }
+    if options.is_enabled(UNBALANCED) {
+        for idx in unbalanced {
+            let columns = offsets[*idx]..offsets[idx + 1];
+            let quote = line[columns.clone()].to_string();
+            ret.push((columns, quote.clone(), quote, UNBALANCED, false));
+        }
+    }
+    ret.sort_by_key(|(columns, ..)| columns.start);
+    ret
+}
+
+/// a normalized line along with where its chars come from
+pub(crate) struct Trace {
+    pub(crate) normalized: Vec<char>,
+    /// the original index of every char, `None` for inserted or replaced chars
+    pub(crate) origins: Vec<Option<usize>>,
+    /// the original chars every char takes the place of, a char replaced by as many chars is
+    /// followed by each of them, and an inserted char sits at the end of the char before
+    pub(crate) sources: Vec<Range<usize>>,
+    pub(crate) edits: Vec<Edit>,
+}
+
+/// normalize the line and follow every change the passes make
+pub(crate) fn trace_line(line: &str, options: &NormalizeOptions, quotes: QuoteContext) -> Trace {
+    let original = line.chars().collect::<Vec<_>>();
+    let mut origins = (0..original.len()).map(Some).collect::<Vec<_>>();
+    let mut sources = (0..original.len())
+        .map(|idx| idx..idx + 1)
+        .collect::<Vec<_>>();
+    let mut edits = vec![];
+    let rules = options.enabled_rules();
+    let mut observe = |pass: Pass, before: &[char], after: &[char]| {
+        let mut new_origins = Vec::with_capacity(after.len());
+        let mut new_sources: Vec<Range<usize>> = Vec::with_capacity(after.len());
+        for op in capture_diff_slices(Algorithm::Myers, before, after) {
+            let (tag, old, new) = op.as_tag_tuple();
+            if tag == DiffTag::Equal {
+                new_origins.extend_from_slice(&origins[old.clone()]);
+                new_sources.extend_from_slice(&sources[old]);
+                continue;
+            }
+            if old.len() == new.len() {
+                new_sources.extend_from_slice(&sources[old.clone()]);
+            } else {
+                let source = match (sources[old.clone()].first(), sources[old.clone()].last()) {
+                    (Some(first), Some(last)) => first.start..last.end,
+                    _ => {
+                        let end = new_sources.last().map_or(0, |it| it.end);
+                        end..end
+                    }
+                };
+                new_sources.extend(new.clone().map(|_| source.clone()));
+            }
+
+            let kept = origins[old.clone()]
+                .iter()
+                .flatten()
+                .copied()
+                .collect::<Vec<_>>();
+            let span = match (kept.first(), kept.last()) {
+                (Some(first), Some(last)) => *first..*last + 1,
+                _ => {
+                    let anchor = anchor(&origins, old.start, original.len());
+                    anchor..anchor
+                }
+            };
+            edits.push(Edit {
+                span,
+                rule: rule_id(&rules, pass, before, old, &after[new.clone()]),
+            });
+            new_origins.extend(new.map(|_| None));
+        }
+        origins = new_origins;
+        sources = new_sources;
+    };
+    let normalized = process_line(line, options, quotes, Some(&mut observe))
+        .unwrap_or_else(|diverged| diverged.output);
+    Trace {
+        normalized,
+        origins,
+        sources,
+        edits,
+    }
+}
+
+/// the first edit overlapping the changed range, or touching it when none overlaps
+fn find_rule(edits: &[Edit], range: Range<usize>) -> &'static str {
+    let overlapping = |edit: &&Edit| {
+        if range.is_empty() {
+            edit.span.start <= range.start && range.start <= edit.span.end
+        } else if edit.span.is_empty() {
+            range.start < edit.span.start && edit.span.start < range.end
+        } else {
+            edit.span.start < range.end && range.start < edit.span.end
+        }
+    };
+    let touching = |edit: &&Edit| edit.span.start <= range.end && range.start <= edit.span.end;
+    edits
+        .iter()
+        .find(overlapping)
+        .or_else(|| edits.iter().find(touching))
+        .map_or(Pass::Collapse.id(), |edit| edit.rule)
+}
+
+/// the original index a change at `idx` of the current text is attached to
+fn anchor(origins: &[Option<usize>], idx: usize, len: usize) -> usize {
+    origins[idx..]
+        .iter()
+        .flatten()
+        .next()
+        .copied()
+        .or_else(|| origins[..idx].iter().flatten().last().map(|it| it + 1))
+        .unwrap_or(len)
+}
+
+/// name the spacing rule which inserted or removed a space, the pass itself otherwise
+fn rule_id(
+    rules: &RuleSet,
+    pass: Pass,
+    before: &[char],
+    old: Range<usize>,
+    new: &[char],
+) -> &'static str {
+    let inserted = old.is_empty() && new == [' '];
+    let removed = new.is_empty() && before[old.clone()] == [' '];
+    let action = match pass {
+        Pass::Space if inserted => SpaceAction::Add,
+        Pass::Space if removed => SpaceAction::Remove,
+        Pass::MinorSpace if inserted => SpaceAction::Minor,
+        Pass::PuncStyle => return style_rule(before, old, new),
+        _ => return pass.id(),
+    };
+    let left = old.start.checked_sub(1).and_then(|idx| before.get(idx));
+    let right = before.get(old.end);
+    left.zip(right)
+        .and_then(|(left, right)| rules.find(action, left, right))
+        .map(|rule| rule.id)
+        .unwrap_or(pass.id())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{lint, lint_with, normalize, NormalizeOptions};
+
+    #[test]
+    fn should_report_nothing_given_normalized_content() {
+        assert!(lint("中文 abc，中文\nhello world").is_empty());
+    }
+
+    #[test]
+    fn should_report_spacing_rule() {
+        let diagnostics = lint("中文abc");
+        assert_eq!(1, diagnostics.len());
+        assert_eq!("space/minor/zh-letter+en-letter", diagnostics[0].rule);
+        assert_eq!(6..6, diagnostics[0].columns);
+        assert_eq!("", diagnostics[0].original);
+        assert_eq!(" ", diagnostics[0].replacement);
+
+        let diagnostics = lint("中文 中文");
+        assert_eq!(1, diagnostics.len());
+        assert_eq!("space/remove/zh-char+zh-char", diagnostics[0].rule);
+        assert_eq!(6..7, diagnostics[0].columns);
+    }
+
+    #[test]
+    fn should_report_pass_with_position() {
+        let diagnostics = lint("hello\n中文,中文");
+        assert_eq!(1, diagnostics.len());
+        assert_eq!("punc/zh", diagnostics[0].rule);
+        assert_eq!(1, diagnostics[0].line);
+        assert_eq!(6..7, diagnostics[0].columns);
+        assert_eq!(12..13, diagnostics[0].offset);
+        assert_eq!(",", diagnostics[0].original);
+        assert_eq!("，", diagnostics[0].replacement);
+
+        let diagnostics = lint("你好「世界」");
+        assert_eq!(
+            vec!["quote/zh-style", "quote/zh-style"],
+            diagnostics.iter().map(|it| it.rule).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn should_give_normalized_content_when_applying_all_diagnostics() {
+        for content in [
+            "他们说:\"你好啊\"",
+            "hello（world）",
+            "中文…...中文...",
+            "商品123.00元",
+            "  hello  ｗorld  ",
+            "真的吗？。苹果、香蕉、和梨，，《读《呐喊》有感》",
+        ] {
+            let mut fixed = content.to_string();
+            for diagnostic in lint(content).iter().filter(|it| it.fixed).rev() {
+                fixed.replace_range(diagnostic.offset.clone(), &diagnostic.replacement);
+            }
+            assert_eq!(normalize(content), fixed);
+        }
+    }
+
+    #[test]
+    fn should_skip_indentation_when_preserving_layout() {
+        let options = NormalizeOptions::builder().preserve_layout(true).build();
+        let diagnostics = lint_with("  中文abc\r\n", &options);
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(8..8, diagnostics[0].columns);
+        assert_eq!(2, lint("  中文abc\r\n").len());
+    }
+}
@@ -0,0 +1,113 @@
+use crate::{NormalizeOptions, ParseOptionError, ZhPeriod, ZhQuote};
+use std::fmt;
+use std::str::FromStr;
+
+/// the quotes and the ellipsis chinese text uses in a region.
+///
+/// only the marks which differ between the regions are covered. the centered punctuations of
+/// taiwan and hong kong are the same chars placed by the font, the dash `——` and the brackets
+/// `（）` are the same in all three regions, so no profile rewrites them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Locale {
+    /// simplified chinese in mainland china, `“”` quotes and `……` ellipsis, the default
+    ZhHansCn,
+    /// traditional chinese in taiwan, `「」` quotes and the centered `⋯⋯` ellipsis
+    ZhHantTw,
+    /// traditional chinese in hong kong, `「」` quotes
+    ZhHantHk,
+}
+
+impl Locale {
+    /// the options bundled by the profile, other options keep their defaults
+    pub fn options(self) -> NormalizeOptions {
+        let default = NormalizeOptions::default();
+        match self {
+            Locale::ZhHansCn => default,
+            Locale::ZhHantTw => NormalizeOptions {
+                zh_period: ZhPeriod::Empty,
+                zh_quote: ZhQuote::Rect,
+                zh_ellipsis: "⋯⋯".to_string(),
+                ..default
+            },
+            Locale::ZhHantHk => NormalizeOptions {
+                zh_period: ZhPeriod::Empty,
+                zh_quote: ZhQuote::Rect,
+                ..default
+            },
+        }
+    }
+
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Locale::ZhHansCn => "zh-Hans-CN",
+            Locale::ZhHantTw => "zh-Hant-TW",
+            Locale::ZhHantHk => "zh-Hant-HK",
+        }
+    }
+}
+
+impl fmt::Display for Locale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.tag())
+    }
+}
+
+/// accepts the language tags case insensitively, with or without the script
+impl FromStr for Locale {
+    type Err = ParseOptionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().replace('_', "-").as_str() {
+            "zh-hans-cn" | "zh-cn" | "zh-hans" => Ok(Locale::ZhHansCn),
+            "zh-hant-tw" | "zh-tw" => Ok(Locale::ZhHantTw),
+            "zh-hant-hk" | "zh-hk" => Ok(Locale::ZhHantHk),
+            _ => Err(ParseOptionError::new("locale", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{normalize_with, Locale, NormalizeOptions, ZhQuote};
+
+    #[test]
+    fn should_parse_locale_tags() {
+        assert_eq!(Ok(Locale::ZhHantTw), "zh-Hant-TW".parse());
+        assert_eq!(Ok(Locale::ZhHantHk), "zh_HK".parse());
+        assert_eq!(Ok(Locale::ZhHansCn), "zh-CN".parse());
+        assert!("ja-JP".parse::<Locale>().is_err());
+    }
+
+    #[test]
+    fn should_normalize_by_locale() {
+        let content = "他說:\"你好\"...我們用Rust";
+        assert_eq!(
+            "他說：“你好”……我們用 Rust",
+            normalize_with(content, &Locale::ZhHansCn.options())
+        );
+        assert_eq!(
+            "他說：「你好」⋯⋯我們用 Rust",
+            normalize_with(content, &Locale::ZhHantTw.options())
+        );
+        assert_eq!(
+            "他說：「你好」……我們用 Rust",
+            normalize_with(content, &Locale::ZhHantHk.options())
+        );
+        // brackets are the same in every region
+        for locale in [Locale::ZhHansCn, Locale::ZhHantTw, Locale::ZhHantHk] {
+            assert_eq!(
+                "中文（注释）",
+                normalize_with("中文(注释)", &locale.options())
+            );
+        }
+        // a profile is a starting point, options set afterwards win
+        let options = NormalizeOptions::builder()
+            .locale(Locale::ZhHantTw)
+            .zh_quote(ZhQuote::Curly)
+            .build();
+        assert_eq!(
+            "他說：“你好”⋯⋯我們用 Rust",
+            normalize_with(content, &options)
+        );
+    }
+}
@@ -0,0 +1,326 @@
+use chineseword::{normalize_with, Config, Locale, NormalizeOptions};
+use clap::Parser;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use similar::TextDiff;
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process::ExitCode;
+use walkdir::WalkDir;
+
+static DEFAULT_INCLUDE: [&str; 4] = ["*.md", "*.markdown", "*.txt", "*.text"];
+
+/// normalize the spaces and punctuations between chinese and english words
+#[derive(Debug, Parser)]
+#[command(name = "chineseword", version)]
+struct Cli {
+    /// files or directories to normalize, read from stdin when omitted or given `-`
+    paths: Vec<PathBuf>,
+
+    /// rewrite the files in place
+    #[arg(long, conflicts_with = "check")]
+    fix: bool,
+
+    /// exit with a non-zero code when any input would be changed
+    #[arg(long)]
+    check: bool,
+
+    /// print a unified diff of the changes
+    #[arg(long)]
+    diff: bool,
+
+    /// read the options from a toml file instead of the `.chineseword.toml` files found from
+    /// every input upward
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// the locale profile, one of `zh-Hans-CN`, `zh-Hant-TW` or `zh-Hant-HK`, overriding the one
+    /// of the config file
+    #[arg(long)]
+    locale: Option<Locale>,
+
+    /// turn on the rules and passes whose id starts with the prefix, can be repeated
+    #[arg(long, value_name = "RULE")]
+    enable: Vec<String>,
+
+    /// turn off the rules and passes whose id starts with the prefix, can be repeated
+    #[arg(long, value_name = "RULE")]
+    disable: Vec<String>,
+
+    /// glob of the files to pick up while walking directories, can be repeated
+    #[arg(long, value_name = "GLOB")]
+    include: Vec<String>,
+
+    /// glob of the files or directories to skip, can be repeated
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+
+    /// treat the inputs as markdown, files ending with `.md` or `.markdown` always are
+    #[cfg(feature = "markdown")]
+    #[arg(long)]
+    markdown: bool,
+}
+
+enum Input {
+    Stdin,
+    File(PathBuf),
+}
+
+impl Input {
+    fn name(&self) -> String {
+        match self {
+            Input::Stdin => "<stdin>".to_string(),
+            Input::File(path) => path.display().to_string(),
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(&cli) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(1),
+        Err(e) => {
+            eprintln!("chineseword: {}", e);
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// returns whether all inputs are already normalized or have been fixed
+fn run(cli: &Cli) -> io::Result<bool> {
+    let include = build_glob_set(if cli.include.is_empty() {
+        DEFAULT_INCLUDE.iter().map(|it| it.to_string()).collect()
+    } else {
+        cli.include.clone()
+    })?;
+    let exclude = build_glob_set(cli.exclude.clone())?;
+    let inputs = collect_inputs(&cli.paths, &include, &exclude)?;
+
+    let explicit = match &cli.config {
+        Some(path) => Some((
+            std::path::absolute(path)?,
+            Config::load(path).map_err(invalid_input)?,
+        )),
+        None => None,
+    };
+    let mut stdout = io::stdout().lock();
+    let mut clean = true;
+    for input in inputs {
+        let options = resolve_options(cli, &input, explicit.as_ref())?;
+        let content = match &input {
+            Input::Stdin => {
+                let mut content = String::new();
+                io::stdin().read_to_string(&mut content)?;
+                content
+            }
+            Input::File(path) => std::fs::read_to_string(path)
+                .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?,
+        };
+        let normalized = normalize_input(cli, &input, &content, &options);
+        let changed = normalized != content;
+
+        if cli.diff && changed {
+            let name = input.name();
+            let diff = TextDiff::from_lines(&content, &normalized);
+            write!(
+                stdout,
+                "{}",
+                diff.unified_diff().header(&name, &name).context_radius(3)
+            )?;
+        }
+        if cli.fix {
+            match &input {
+                Input::File(path) if changed => std::fs::write(path, &normalized)?,
+                Input::Stdin => write!(stdout, "{}", normalized)?,
+                _ => {}
+            }
+        } else if cli.check {
+            if changed {
+                clean = false;
+                if !cli.diff {
+                    eprintln!("{} is not normalized", input.name());
+                }
+            }
+        } else if !cli.diff {
+            write!(stdout, "{}", normalized)?;
+        }
+    }
+    Ok(clean)
+}
+
+fn invalid_input(e: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, e)
+}
+
+/// the config given on the command line along with its overrides matching the input, or the
+/// `.chineseword.toml` files found from the input upward, with the command line flags on top.
+/// stdin is taken as the current directory.
+fn resolve_options(
+    cli: &Cli,
+    input: &Input,
+    explicit: Option<&(PathBuf, Config)>,
+) -> io::Result<NormalizeOptions> {
+    let path = match input {
+        Input::File(path) => std::path::absolute(path)?,
+        Input::Stdin => std::env::current_dir()?,
+    };
+    let mut config = match explicit {
+        Some((file, config)) => {
+            let dir = file.parent().unwrap_or(file);
+            config
+                .for_file(path.strip_prefix(dir).unwrap_or(&path))
+                .map_err(|e| invalid_input(format!("{}: {}", file.display(), e)))?
+        }
+        None => Config::resolve(&path).map_err(invalid_input)?,
+    };
+    if cli.locale.is_some() {
+        config.locale = cli.locale;
+    }
+    // the layout is kept unless the config says otherwise
+    config.preserve_layout = config.preserve_layout.or(Some(true));
+    for id in &cli.enable {
+        config.rules.insert(id.clone(), true);
+    }
+    for id in &cli.disable {
+        config.rules.insert(id.clone(), false);
+    }
+    Ok(config.options())
+}
+
+fn build_glob_set(globs: Vec<String>) -> io::Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        let glob = Glob::new(&glob).map_err(invalid_input)?;
+        builder.add(glob);
+    }
+    builder.build().map_err(invalid_input)
+}
+
+/// expand the command line paths into the list of inputs.
+///
+/// files given explicitly are always taken unless excluded, files found while walking a
+/// directory also need to match the include globs.
+fn collect_inputs(
+    paths: &[PathBuf],
+    include: &GlobSet,
+    exclude: &GlobSet,
+) -> io::Result<Vec<Input>> {
+    if paths.is_empty() {
+        return Ok(vec![Input::Stdin]);
+    }
+    let mut inputs = vec![];
+    for path in paths {
+        if path.as_os_str() == "-" {
+            inputs.push(Input::Stdin);
+        } else if path.is_dir() {
+            let walker = WalkDir::new(path)
+                .sort_by_file_name()
+                .into_iter()
+                .filter_entry(|entry| !is_excluded(exclude, Some(path), entry.path()));
+            for entry in walker {
+                let entry = entry.map_err(io::Error::from)?;
+                if entry.file_type().is_file() && is_included(include, Some(path), entry.path()) {
+                    inputs.push(Input::File(entry.into_path()));
+                }
+            }
+        } else if !is_excluded(exclude, None, path) {
+            inputs.push(Input::File(path.clone()));
+        }
+    }
+    Ok(inputs)
+}
+
+/// whether any glob matches the path as given without `./`, its file name or, for a path found
+/// while walking `root`, the path inside `root` with or without the name of `root` in front. so
+/// `docs/en/**` matches the same files whether `docs`, `./docs` or `repo/docs` is walked.
+fn is_included(include: &GlobSet, root: Option<&Path>, path: &Path) -> bool {
+    let given = path
+        .components()
+        .filter(|it| *it != Component::CurDir)
+        .collect::<PathBuf>();
+    let relative = root.and_then(|root| {
+        let relative = path.strip_prefix(root).ok()?;
+        Some((
+            relative,
+            root.file_name().map(|name| Path::new(name).join(relative)),
+        ))
+    });
+    include.is_match(&given)
+        || path.file_name().is_some_and(|name| include.is_match(name))
+        || relative.is_some_and(|(relative, named)| {
+            include.is_match(relative) || named.is_some_and(|named| include.is_match(named))
+        })
+}
+
+fn is_excluded(exclude: &GlobSet, root: Option<&Path>, path: &Path) -> bool {
+    !exclude.is_empty() && is_included(exclude, root, path)
+}
+
+#[cfg_attr(not(feature = "markdown"), allow(unused_variables))]
+fn normalize_input(cli: &Cli, input: &Input, content: &str, options: &NormalizeOptions) -> String {
+    #[cfg(feature = "markdown")]
+    {
+        let is_markdown = cli.markdown
+            || matches!(input, Input::File(path) if path
+                .extension()
+                .is_some_and(|ext| ext == "md" || ext == "markdown"));
+        if is_markdown {
+            return chineseword::normalize_markdown_with(content, options);
+        }
+    }
+    normalize_with(content, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{build_glob_set, is_excluded, is_included};
+    use std::path::Path;
+
+    #[test]
+    fn should_match_globs_against_path_and_file_name() {
+        let include = build_glob_set(vec!["*.md".to_string()]).unwrap();
+        assert!(is_included(&include, None, Path::new("docs/zh/readme.md")));
+        assert!(!is_included(&include, None, Path::new("docs/zh/main.rs")));
+
+        let exclude = build_glob_set(vec!["docs/en/**".to_string()]).unwrap();
+        assert!(is_excluded(&exclude, None, Path::new("docs/en/readme.md")));
+        assert!(is_excluded(
+            &exclude,
+            None,
+            Path::new("./docs/en/readme.md")
+        ));
+        assert!(!is_excluded(&exclude, None, Path::new("docs/zh/readme.md")));
+
+        let empty = build_glob_set(vec![]).unwrap();
+        assert!(!is_excluded(&empty, None, Path::new("docs/en/readme.md")));
+    }
+
+    #[test]
+    fn should_match_globs_relative_to_walked_directory() {
+        let exclude = build_glob_set(vec!["docs/en/**".to_string()]).unwrap();
+        for (root, path) in [
+            ("docs", "docs/en/readme.md"),
+            ("./docs", "./docs/en/readme.md"),
+            ("repo/docs/", "repo/docs/en/readme.md"),
+            (".", "./docs/en/readme.md"),
+        ] {
+            assert!(
+                is_excluded(&exclude, Some(Path::new(root)), Path::new(path)),
+                "{}",
+                root
+            );
+        }
+        assert!(!is_excluded(
+            &exclude,
+            Some(Path::new("repo/docs")),
+            Path::new("repo/docs/zh/readme.md")
+        ));
+
+        let exclude = build_glob_set(vec!["en/**".to_string()]).unwrap();
+        assert!(is_excluded(
+            &exclude,
+            Some(Path::new("repo/docs")),
+            Path::new("repo/docs/en/readme.md")
+        ));
+    }
+}
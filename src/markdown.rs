@@ -0,0 +1,205 @@
+use crate::directive::{scoped_options, Directives, Scope};
+use crate::quote::{pair_quotes, QuoteContext};
+use crate::{normalize_line, NormalizeOptions};
+use pulldown_cmark::{Event, LinkType, Options, Parser, Tag, TagEnd};
+use std::ops::Range;
+
+pub fn normalize_markdown(content: impl Into<String>) -> String {
+    normalize_markdown_with(content, &NormalizeOptions::default())
+}
+
+/// normalize only the prose of a markdown document.
+///
+/// code blocks, inline code, link destinations, autolinks and raw html are written back
+/// exactly as they appear in the source, and so are the lines skipped by ignore directives.
+pub fn normalize_markdown_with(content: impl Into<String>, options: &NormalizeOptions) -> String {
+    let content = content.into();
+    let mut directives = Directives::default();
+    let mut lines = content
+        .split_inclusive('\n')
+        .map(|line| directives.scope(line));
+    let mut line_start = 0;
+    let mut scope = lines.next().unwrap_or(Scope::Skip);
+
+    let mut ranges = vec![];
+    for range in prose_ranges(&content) {
+        // move to the line the text starts at
+        while let Some(end) = content[line_start..]
+            .find('\n')
+            .map(|it| line_start + it + 1)
+        {
+            if range.start < end {
+                break;
+            }
+            line_start = end;
+            scope = lines.next().unwrap_or(Scope::Skip);
+        }
+        ranges.push((range, scope.clone()));
+    }
+
+    // quotes pair inside a paragraph, a blank entry separates two of them
+    let paragraph_ends = paragraph_ends(&content);
+    let mut texts = vec![];
+    let mut last_end = 0;
+    for (range, scope) in &ranges {
+        if paragraph_ends
+            .iter()
+            .any(|end| (last_end..=range.start).contains(end))
+        {
+            texts.push("");
+        }
+        last_end = range.end;
+        texts.push(match scope {
+            Scope::Skip => "",
+            Scope::Normalize(_) => content[range.clone()].trim(),
+        });
+    }
+    let (contexts, _) = pair_quotes(texts.iter().copied());
+    let mut contexts = texts
+        .iter()
+        .zip(contexts)
+        .filter(|(text, _)| !text.is_empty())
+        .map(|(_, context)| context);
+
+    let mut ret = String::with_capacity(content.len());
+    let mut last = 0;
+    for (range, scope) in ranges {
+        ret.push_str(&content[last..range.start]);
+        last = range.end;
+        let text = &content[range];
+        match scope {
+            Scope::Normalize(disabled) if !text.trim().is_empty() => ret.push_str(&normalize_text(
+                text,
+                &scoped_options(options, &disabled),
+                contexts.next().unwrap_or_default(),
+            )),
+            _ => ret.push_str(text),
+        }
+    }
+    ret.push_str(&content[last..]);
+    ret
+}
+
+/// byte offsets where the blocks holding prose end
+fn paragraph_ends(content: &str) -> Vec<usize> {
+    Parser::new_ext(content, parser_options())
+        .into_offset_iter()
+        .filter(|(event, _)| {
+            matches!(
+                event,
+                Event::End(
+                    TagEnd::Paragraph | TagEnd::Heading(_) | TagEnd::Item | TagEnd::TableCell
+                )
+            )
+        })
+        .map(|(_, range)| range.end)
+        .collect()
+}
+
+/// byte ranges of the text nodes which can be normalized as prose.
+///
+/// adjacent text nodes are merged, since the parser splits text at every character which
+/// might start an inline element.
+pub(crate) fn prose_ranges(content: &str) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = vec![];
+    let mut verbatim_depth = 0;
+    for (event, range) in Parser::new_ext(content, parser_options()).into_offset_iter() {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) | Event::Start(Tag::MetadataBlock(_)) => {
+                verbatim_depth += 1
+            }
+            Event::Start(Tag::Link {
+                link_type: LinkType::Autolink | LinkType::Email,
+                ..
+            }) => verbatim_depth += 1,
+            Event::End(TagEnd::CodeBlock) | Event::End(TagEnd::MetadataBlock(_)) => {
+                verbatim_depth -= 1
+            }
+            Event::End(TagEnd::Link) if verbatim_depth > 0 => verbatim_depth -= 1,
+            Event::Text(text) if verbatim_depth == 0 => {
+                // escaped characters and entities are not the same as their source, skip them
+                if content[range.clone()] != *text {
+                    continue;
+                }
+                match ranges.last_mut() {
+                    Some(last) if last.end == range.start => last.end = range.end,
+                    _ => ranges.push(range),
+                }
+            }
+            _ => {}
+        }
+    }
+    ranges
+}
+
+fn parser_options() -> Options {
+    Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+}
+
+/// normalize a text node but keep the whitespace separating it from its neighbours
+fn normalize_text(text: &str, options: &NormalizeOptions, quotes: QuoteContext) -> String {
+    let inner = text.trim();
+    if inner.is_empty() {
+        return text.to_string();
+    }
+    let leading = &text[..text.len() - text.trim_start().len()];
+    let trailing = &text[text.trim_end().len()..];
+    format!(
+        "{}{}{}",
+        leading,
+        normalize_line(inner, options, quotes),
+        trailing
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::normalize_markdown;
+
+    #[test]
+    fn should_normalize_prose() {
+        assert_eq!(
+            "# 标题 abc\n\n中文，中文",
+            normalize_markdown("# 标题abc\n\n中文,中文")
+        );
+        assert_eq!(
+            "- 中文 abc\n- 中文（中文）\n",
+            normalize_markdown("- 中文abc\n- 中文(中文)\n")
+        );
+    }
+
+    #[test]
+    fn should_keep_code_untouched() {
+        let content = "中文abc\n\n```rust\nlet a=b,c;  // 中文abc\n```\n";
+        assert_eq!(
+            "中文 abc\n\n```rust\nlet a=b,c;  // 中文abc\n```\n",
+            normalize_markdown(content)
+        );
+        assert_eq!("使用 `a=b,c` 命令", normalize_markdown("使用 `a=b,c` 命令"));
+        assert_eq!("    let a=b,c;\n", normalize_markdown("    let a=b,c;\n"));
+    }
+
+    #[test]
+    fn should_keep_links_untouched() {
+        assert_eq!(
+            "参见 [文档 abc](https://example.com/a-b?x=1)",
+            normalize_markdown("参见 [文档abc](https://example.com/a-b?x=1)")
+        );
+        assert_eq!(
+            "参见 <https://example.com/a-b?x=1>",
+            normalize_markdown("参见 <https://example.com/a-b?x=1>")
+        );
+    }
+
+    #[test]
+    fn should_keep_html_untouched() {
+        let content = "<div class=\"a-b\">中文abc</div>\n\n中文<span a=\"b,c\">abc</span>";
+        assert_eq!(
+            "<div class=\"a-b\">中文abc</div>\n\n中文<span a=\"b,c\">abc</span>",
+            normalize_markdown(content)
+        );
+    }
+}
@@ -0,0 +1,205 @@
+use crate::directive::{scoped_options, Scope};
+use crate::lint::trace_line;
+use crate::{split_lines, NormalizeOptions};
+use std::ops::Range;
+
+/// what the offsets given to an [`OffsetMap`] count
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OffsetUnit {
+    Char,
+    /// utf-8 bytes, an offset inside a char counts as the start of the char
+    Byte,
+}
+
+/// where the offsets of the content end up in the normalized content and back, see
+/// [`normalize_with_map`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OffsetMap {
+    /// the input chars every output char takes the place of, inserted chars have an empty span
+    /// where they were inserted
+    spans: Vec<Range<usize>>,
+    /// the byte offset of every input char, followed by the length of the input
+    input: Vec<usize>,
+    /// the byte offset of every output char, followed by the length of the output
+    output: Vec<usize>,
+}
+
+impl OffsetMap {
+    /// the offset of the output an offset of the input moved to. an offset inside replaced or
+    /// removed text moves to the start of what took its place, and text inserted at the offset
+    /// ends up before it.
+    pub fn to_output(&self, offset: usize, unit: OffsetUnit) -> usize {
+        let offset = to_chars(&self.input, offset, unit);
+        let mapped = self.spans.partition_point(|span| span.end <= offset);
+        from_chars(&self.output, mapped, unit)
+    }
+
+    /// the offset of the input an offset of the output comes from. an offset inside inserted or
+    /// replacing text moves to the start of what it took the place of.
+    pub fn to_input(&self, offset: usize, unit: OffsetUnit) -> usize {
+        let offset = to_chars(&self.output, offset, unit);
+        let mapped = self
+            .spans
+            .get(offset)
+            .map_or(self.input.len() - 1, |span| span.start);
+        from_chars(&self.input, mapped, unit)
+    }
+}
+
+/// the char index of an offset, given the byte offsets of the chars
+fn to_chars(offsets: &[usize], offset: usize, unit: OffsetUnit) -> usize {
+    let chars = offsets.len() - 1;
+    match unit {
+        OffsetUnit::Char => offset.min(chars),
+        OffsetUnit::Byte => offsets.partition_point(|it| *it <= offset) - 1,
+    }
+}
+
+fn from_chars(offsets: &[usize], idx: usize, unit: OffsetUnit) -> usize {
+    match unit {
+        OffsetUnit::Char => idx,
+        OffsetUnit::Byte => offsets[idx],
+    }
+}
+
+fn char_offsets(content: &str) -> Vec<usize> {
+    content
+        .char_indices()
+        .map(|(idx, _)| idx)
+        .chain(Some(content.len()))
+        .collect()
+}
+
+/// same as [`normalize_with`](crate::normalize_with), along with a map of the offsets of the
+/// content to the offsets of the result, so cursors and annotations can follow the changes.
+///
+/// ```
+/// use chineseword::{normalize_with_map, NormalizeOptions, OffsetUnit};
+///
+/// let (normalized, map) = normalize_with_map("中文abc...", &NormalizeOptions::default());
+/// assert_eq!("中文 abc……", normalized);
+/// // `b` moves right after the inserted space
+/// assert_eq!(4, map.to_output(3, OffsetUnit::Char));
+/// assert_eq!(8, map.to_output(7, OffsetUnit::Byte));
+/// // an offset inside `...` moves to the start of the `……` replacing it
+/// assert_eq!(6, map.to_output(6, OffsetUnit::Char));
+/// assert_eq!(3, map.to_input(4, OffsetUnit::Char));
+/// ```
+pub fn normalize_with_map(content: &str, options: &NormalizeOptions) -> (String, OffsetMap) {
+    let input = char_offsets(content);
+    // the char index a part of a line starts at
+    let start = |slice: &str| {
+        let offset = slice.as_ptr() as usize - content.as_ptr() as usize;
+        input.partition_point(|it| *it < offset)
+    };
+    let mut output = String::with_capacity(content.len());
+    let mut spans = Vec::with_capacity(input.len());
+    let keep = |output: &mut String, spans: &mut Vec<Range<usize>>, slice: &str| {
+        // the layout left out of a line is not a part of the content
+        if slice.is_empty() {
+            return;
+        }
+        let start = start(slice);
+        for (idx, c) in slice.chars().enumerate() {
+            output.push(c);
+            spans.push(start + idx..start + idx + 1);
+        }
+    };
+
+    for (idx, line) in split_lines(content, options).iter().enumerate() {
+        if idx > 0 && !options.preserve_layout {
+            // the `\n` ending the line before, the `\r` of a `\r\n` is removed
+            let newline = start(line.text) - 1;
+            output.push('\n');
+            spans.push(newline..newline + 1);
+        }
+        keep(&mut output, &mut spans, line.indent);
+        match &line.scope {
+            Scope::Skip => keep(&mut output, &mut spans, line.text),
+            Scope::Normalize(disabled) => {
+                let line_start = start(line.text);
+                let options = scoped_options(options, disabled);
+                let trace = trace_line(line.text, &options, line.quotes);
+                output.extend(&trace.normalized);
+                spans.extend(
+                    trace
+                        .sources
+                        .into_iter()
+                        .map(|span| line_start + span.start..line_start + span.end),
+                );
+            }
+        }
+        keep(&mut output, &mut spans, line.ending);
+    }
+
+    let map = OffsetMap {
+        spans,
+        input,
+        output: char_offsets(&output),
+    };
+    (output, map)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{normalize_with, normalize_with_map, NormalizeOptions, OffsetUnit};
+    use proptest::prelude::*;
+
+    #[test]
+    fn should_map_offsets_across_changes() {
+        let options = NormalizeOptions::default();
+        let content = "你好world\r\n世界,你好...ｗ";
+        let (normalized, map) = normalize_with_map(content, &options);
+        assert_eq!("你好 world\n世界，你好……w", normalized);
+        // `w` moves right after the inserted space, which maps back to where it was inserted
+        assert_eq!(3, map.to_output(2, OffsetUnit::Char));
+        assert_eq!(2, map.to_input(2, OffsetUnit::Char));
+        // the removed `\r` moves to the `\n` kept after it
+        assert_eq!(8, map.to_output(7, OffsetUnit::Char));
+        // offsets inside `...` move to the start of the `……` replacing it
+        assert_eq!(
+            vec![14, 14, 14, 16],
+            (14..18)
+                .map(|offset| map.to_output(offset, OffsetUnit::Char))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(14, map.to_input(15, OffsetUnit::Char));
+        // the full width `ｗ` takes three bytes and its replacement one
+        let offset = content.len() - "ｗ".len();
+        assert_eq!(
+            normalized.len() - 1,
+            map.to_output(offset, OffsetUnit::Byte)
+        );
+        assert_eq!(offset, map.to_input(normalized.len() - 1, OffsetUnit::Byte));
+        assert_eq!(
+            normalized.len(),
+            map.to_output(usize::MAX, OffsetUnit::Byte)
+        );
+
+        let options = NormalizeOptions::builder().preserve_layout(true).build();
+        let (normalized, map) = normalize_with_map("  中文abc\r\n", &options);
+        assert_eq!("  中文 abc\r\n", normalized);
+        assert_eq!(5, map.to_output(4, OffsetUnit::Char));
+        // an offset inside a char counts as its start
+        assert_eq!(2, map.to_input(3, OffsetUnit::Byte));
+    }
+
+    proptest! {
+        #[test]
+        fn should_map_offsets_in_order(content in "[中文a-zｗ１ ,.…\"“”\r\n]{0,32}") {
+            let options = NormalizeOptions::default();
+            let (normalized, map) = normalize_with_map(&content, &options);
+            prop_assert_eq!(normalize_with(content.as_str(), &options), normalized.as_str());
+            let input = content.chars().count();
+            let output = normalized.chars().count();
+            prop_assert_eq!(output, map.to_output(input, OffsetUnit::Char));
+            prop_assert_eq!(input, map.to_input(output, OffsetUnit::Char));
+            for offset in 1..=input {
+                prop_assert!(
+                    map.to_output(offset - 1, OffsetUnit::Char)
+                        <= map.to_output(offset, OffsetUnit::Char)
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,357 @@
+use std::ops::Range;
+
+/// protected chars are replaced by `PLACEHOLDER_BASE + index` while the passes run, the
+/// supplementary private use area is not expected in real text
+const PLACEHOLDER_BASE: u32 = 0xF0000;
+const PLACEHOLDER_END: u32 = 0xFFFFD;
+
+/// names whose punctuations are part of the name, the longer ones come first
+static TECH_TERMS: [&str; 22] = [
+    "Objective-C",
+    "ASP.NET",
+    "PL/SQL",
+    "TCP/IP",
+    "UDP/IP",
+    "Node.js",
+    "Vue.js",
+    "Next.js",
+    "React.js",
+    "Wi-Fi",
+    "UTF-16",
+    "UTF-8",
+    "C/C++",
+    ".NET",
+    "I/O",
+    "C++",
+    "G++",
+    "C#",
+    "F#",
+    "J#",
+    "A#",
+    "Q#",
+];
+
+/// characters a path, an email address, a domain name or an identifier is made of
+fn is_path_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "._~/\\-+@%:".contains(c)
+}
+
+fn is_url_char(c: char) -> bool {
+    c.is_ascii_graphic() && !"\"<>\\^`{|}".contains(c)
+}
+
+fn is_scheme_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "+.-".contains(c)
+}
+
+pub(crate) fn is_placeholder(c: &char) -> bool {
+    (PLACEHOLDER_BASE..=PLACEHOLDER_END).contains(&(*c as u32))
+}
+
+/// the original chars of a line whose protected spans are replaced by placeholders
+pub(crate) struct Protected {
+    originals: Vec<char>,
+}
+
+impl Protected {
+    pub(crate) fn restore(&self, chars: Vec<char>) -> Vec<char> {
+        if self.originals.is_empty() {
+            return chars;
+        }
+        chars
+            .into_iter()
+            .map(|c| {
+                if is_placeholder(&c) {
+                    self.originals[(c as u32 - PLACEHOLDER_BASE) as usize]
+                } else {
+                    c
+                }
+            })
+            .collect()
+    }
+}
+
+/// replace every char of the urls, email addresses, paths, domain names, compound words, tech
+/// terms and numeric tokens by a placeholder,
+/// which the passes treat as an english letter, so they are kept verbatim.
+///
+/// placeholders keep the length of the line, so positions do not move.
+pub(crate) fn protect(mut chars: Vec<char>) -> (Vec<char>, Protected) {
+    let mut originals = vec![];
+    if chars.iter().any(is_placeholder) {
+        return (chars, Protected { originals });
+    }
+    for range in protected_spans(&chars) {
+        for c in &mut chars[range] {
+            let Some(placeholder) =
+                char::from_u32(PLACEHOLDER_BASE + originals.len() as u32).filter(is_placeholder)
+            else {
+                break;
+            };
+            originals.push(*c);
+            *c = placeholder;
+        }
+    }
+    (chars, Protected { originals })
+}
+
+/// char ranges of the urls, email addresses, paths, domain names, compound words, tech terms
+/// and numeric tokens in the line
+pub(crate) fn protected_spans(chars: &[char]) -> Vec<Range<usize>> {
+    let mut ret = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        if let Some(end) = url_end(chars, i).or_else(|| tech_term_end(chars, i)) {
+            ret.push(i..end);
+            i = end;
+            continue;
+        }
+        if !is_path_char(chars[i]) {
+            i += 1;
+            continue;
+        }
+        let mut end = i;
+        while end < chars.len() && is_path_char(chars[end]) && url_end(chars, end).is_none() {
+            end += 1;
+        }
+        let mut start = i;
+        i = end;
+        while end > start && ".:".contains(chars[end - 1]) {
+            end -= 1;
+        }
+        while start < end && chars[start] == ':' {
+            start += 1;
+        }
+        let token = chars[start..end].iter().collect::<String>();
+        if is_email(&token)
+            || is_path(&token)
+            || is_domain(&token)
+            || is_compound_word(&token)
+            || is_numeric(&token)
+        {
+            ret.push(start..end);
+        }
+    }
+    ret
+}
+
+/// the end of the url starting at `start`, a url needs a scheme followed by `://`
+fn url_end(chars: &[char], start: usize) -> Option<usize> {
+    if (start > 0 && is_scheme_char(chars[start - 1])) || !chars[start].is_ascii_alphabetic() {
+        return None;
+    }
+    let mut i = start;
+    while i < chars.len() && is_scheme_char(chars[i]) {
+        i += 1;
+    }
+    if chars.get(i..i + 3) != Some(&[':', '/', '/']) {
+        return None;
+    }
+    let mut end = i + 3;
+    while end < chars.len() && is_url_char(chars[end]) {
+        end += 1;
+    }
+    // trailing punctuations and unbalanced closing brackets belong to the sentence
+    loop {
+        match chars[end - 1] {
+            '.' | ',' | ';' | ':' | '!' | '?' | '\'' => end -= 1,
+            ')' if count(&chars[start..end], '(') < count(&chars[start..end], ')') => end -= 1,
+            ']' if count(&chars[start..end], '[') < count(&chars[start..end], ']') => end -= 1,
+            _ => break,
+        }
+    }
+    (end > i + 3).then_some(end)
+}
+
+/// the end of the tech term starting at `start`, like `C++` or `Node.js`
+fn tech_term_end(chars: &[char], start: usize) -> Option<usize> {
+    if start > 0 && chars[start - 1].is_ascii_alphanumeric() {
+        return None;
+    }
+    TECH_TERMS.iter().find_map(|term| {
+        let end = start + term.chars().count();
+        let matched = chars.get(start..end)?.iter().copied().eq(term.chars());
+        let bounded = chars
+            .get(end)
+            .is_none_or(|c| !c.is_ascii_alphanumeric() && !"+#".contains(*c));
+        (matched && bounded).then_some(end)
+    })
+}
+
+fn count(chars: &[char], c: char) -> usize {
+    chars.iter().filter(|it| **it == c).count()
+}
+
+fn is_email(token: &str) -> bool {
+    match token.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && local
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "._%+-".contains(c))
+                && is_domain(domain)
+        }
+        None => false,
+    }
+}
+
+fn is_path(token: &str) -> bool {
+    (token.contains('/') || token.contains('\\'))
+        && token.chars().any(|c| c.is_ascii_alphabetic())
+        && !token.contains('@')
+}
+
+/// letters and digits joined by `-` or `_`, like `well-known`, `snake_case`, `x86_64` or a
+/// command line flag like `--verbose`
+fn is_compound_word(token: &str) -> bool {
+    let word = token.trim_start_matches('-');
+    token.len() - word.len() <= 2
+        && (token.contains('-') || token.contains('_'))
+        && word.chars().any(|c| c.is_ascii_alphabetic())
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        && !word.ends_with('-')
+        && !word.contains("--")
+}
+
+/// version numbers, decimals, clock times, dates, ip addresses and ratios, like `v1.2.3`,
+/// `1.2.3-beta.1`, `3:30`, `2024-01-05`, `192.168.0.1`, `fe80::1` or `16:9`
+fn is_numeric(token: &str) -> bool {
+    let is_ipv6 = token.matches(':').count() >= 2
+        && token.chars().any(|c| c.is_ascii_digit())
+        && token.chars().all(|c| c.is_ascii_hexdigit() || c == ':');
+    if is_ipv6 {
+        return true;
+    }
+    let is_numbers = |token: &str| {
+        let groups = token.split(['.', ':', '-', '/']).collect::<Vec<_>>();
+        groups.len() >= 2
+            && groups
+                .iter()
+                .all(|group| !group.is_empty() && group.chars().all(|c| c.is_ascii_digit()))
+    };
+    let token = token.strip_prefix(['v', 'V']).unwrap_or(token);
+    if is_numbers(token) {
+        return true;
+    }
+    // semver with pre-release or build metadata
+    match token.split_once(['-', '+']) {
+        Some((core, rest)) => {
+            is_numbers(core)
+                && !core.contains(['-', '/', ':'])
+                && !rest.is_empty()
+                && rest
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || ".-+".contains(c))
+        }
+        None => false,
+    }
+}
+
+/// `example.com`, `docs.rs:443`, the top level domain has to be in lowercase
+fn is_domain(token: &str) -> bool {
+    let host = match token.rsplit_once(':') {
+        Some((host, port)) if !port.is_empty() && port.chars().all(|c| c.is_ascii_digit()) => host,
+        Some(_) => return false,
+        None => token,
+    };
+    let labels = host.split('.').collect::<Vec<_>>();
+    let tld = labels[labels.len() - 1];
+    labels.len() >= 2
+        && labels.iter().all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+        && tld.len() >= 2
+        && tld.chars().all(|c| c.is_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::normalize;
+    use crate::protect::protected_spans;
+
+    fn spans(text: &str) -> Vec<String> {
+        let chars = text.chars().collect::<Vec<_>>();
+        protected_spans(&chars)
+            .into_iter()
+            .map(|range| chars[range].iter().collect())
+            .collect()
+    }
+
+    #[test]
+    fn should_find_protected_spans() {
+        assert_eq!(
+            vec!["https://example.com/a-b?x=1&y=(2)"],
+            spans("见(https://example.com/a-b?x=1&y=(2)).")
+        );
+        assert_eq!(vec!["me@example.com"], spans("联系me@example.com。"));
+        assert_eq!(
+            vec!["src/lib.rs", "C:\\Windows"],
+            spans("修改src/lib.rs和C:\\Windows")
+        );
+        assert_eq!(
+            vec!["docs.rs", "example.com:8080"],
+            spans("see docs.rs. or example.com:8080")
+        );
+        assert!(spans("e.g. U.S. end.Next wait...what a-").is_empty());
+    }
+
+    #[test]
+    fn should_keep_protected_spans_verbatim() {
+        assert_eq!(
+            "访问 https://example.com/a-b?x=1 获取",
+            normalize("访问https://example.com/a-b?x=1获取")
+        );
+        assert_eq!("修改 src/lib.rs 文件", normalize("修改src/lib.rs文件"));
+        assert_eq!("发送到 me@example.com.", normalize("发送到me@example.com."));
+        assert_eq!(
+            "see docs.rs and src/main.rs",
+            normalize("see docs.rs and src/main.rs")
+        );
+        assert_eq!(
+            "打开 https://a.com/b_c，然后",
+            normalize("打开https://a.com/b_c，然后")
+        );
+    }
+
+    #[test]
+    fn should_keep_compound_words_and_tech_terms() {
+        assert_eq!(
+            "a well-known snake_case name, x86_64 and --verbose",
+            normalize("a well-known snake_case name, x86_64 and --verbose")
+        );
+        assert_eq!(
+            "我们使用 C++、C#、F# 和 .NET 开发",
+            normalize("我们使用C++、C#、F#和.NET开发")
+        );
+        assert_eq!(
+            "基于 Node.js 和 TCP/IP 的 well-known 服务",
+            normalize("基于Node.js和TCP/IP的well-known服务")
+        );
+        assert_eq!("1 + 2 = 3", normalize("1+2=3"));
+        assert_eq!("a - b", normalize("a - b"));
+    }
+
+    #[test]
+    fn should_keep_numeric_tokens() {
+        assert_eq!(
+            vec!["v1.2.3", "1.2.3-beta.1", "3:30", "2024.01.05", "2024-01-05"],
+            spans("版本v1.2.3和1.2.3-beta.1，下午3:30，2024.01.05或2024-01-05")
+        );
+        assert_eq!(
+            vec!["192.168.0.1", "fe80::1", "16:9", "1/2"],
+            spans("地址192.168.0.1或fe80::1，比例16:9和1/2。")
+        );
+        assert_eq!(
+            "版本 v1.2.3 于 2024-01-05 下午 3:30 发布，比例 16:9，占 1/2。",
+            normalize("版本v1.2.3于2024-01-05下午3:30发布,比例16:9，占1/2。")
+        );
+        assert_eq!(
+            "访问 192.168.0.1 或 fe80::1",
+            normalize("访问192.168.0.1或fe80::1")
+        );
+    }
+}
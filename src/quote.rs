@@ -0,0 +1,223 @@
+use crate::lang::char_langs;
+use crate::Lang;
+
+pub(crate) static UNBALANCED: &str = "quote/unbalanced";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Family {
+    Double,
+    Single,
+    Corner,
+    SingleCorner,
+}
+
+/// the quotes left open by the lines before, in the same paragraph
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct QuoteContext {
+    /// the language of the open double quote
+    pub(crate) double: Option<Lang>,
+    /// the language of the open single quote
+    pub(crate) single: Option<Lang>,
+    /// the quotes of the paragraph cannot be paired, so they are left as they are
+    pub(crate) unbalanced: bool,
+}
+
+/// a quote which cannot be paired, as the line and the char index inside it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Unbalanced {
+    pub(crate) line: usize,
+    pub(crate) idx: usize,
+}
+
+/// pair the quotes of every paragraph with a stack, so a quotation may span several lines and
+/// nest `‘’` in `“”` or `『』` in `「」`. paragraphs are separated by blank lines.
+///
+/// returns the context every line starts with and the quotes left unpaired, a paragraph having
+/// any of them is not paired at all.
+pub(crate) fn pair_quotes<'a>(
+    lines: impl IntoIterator<Item = &'a str>,
+) -> (Vec<QuoteContext>, Vec<Unbalanced>) {
+    let mut contexts = vec![];
+    let mut unbalanced = vec![];
+    let mut paragraph_start = 0;
+    let mut pairer = QuotePairer::default();
+    let mut finish = |contexts: &mut Vec<QuoteContext>, pairer: &mut QuotePairer, start: usize| {
+        let mut paragraph_unbalanced = pairer.finish();
+        if !paragraph_unbalanced.is_empty() {
+            for context in &mut contexts[start..] {
+                *context = QuoteContext {
+                    unbalanced: true,
+                    ..QuoteContext::default()
+                };
+            }
+            unbalanced.append(&mut paragraph_unbalanced);
+        }
+    };
+
+    for (line_no, line) in lines.into_iter().enumerate() {
+        if line.trim().is_empty() {
+            finish(&mut contexts, &mut pairer, paragraph_start);
+            contexts.push(QuoteContext::default());
+            paragraph_start = line_no + 1;
+            continue;
+        }
+        contexts.push(pairer.context());
+        pairer.push(line_no, line);
+    }
+    let start = paragraph_start.min(contexts.len());
+    finish(&mut contexts, &mut pairer, start);
+    (contexts, unbalanced)
+}
+
+/// the quotes of a paragraph paired as its lines come, see [`pair_quotes`]
+#[derive(Debug, Default)]
+pub(crate) struct QuotePairer {
+    stack: Vec<(Family, Lang, Unbalanced)>,
+    unbalanced: Vec<Unbalanced>,
+}
+
+impl QuotePairer {
+    /// the quotes left open by the lines pushed so far, as long as the paragraph turns out to
+    /// be balanced
+    pub(crate) fn context(&self) -> QuoteContext {
+        // the passes only pair curly and straight quotes, corner brackets are left as they are
+        let open = |family| {
+            self.stack
+                .iter()
+                .rev()
+                .find(|(it, _, _)| *it == family)
+                .map(|(_, lang, _)| *lang)
+        };
+        QuoteContext {
+            double: open(Family::Double),
+            single: open(Family::Single),
+            unbalanced: false,
+        }
+    }
+
+    /// pair the quotes of the next line of the paragraph
+    pub(crate) fn push(&mut self, line_no: usize, line: &str) {
+        let stack = &mut self.stack;
+        let chars = line.chars().collect::<Vec<_>>();
+        let langs = char_langs(&chars);
+        for (idx, c) in chars.iter().enumerate() {
+            let quote = Unbalanced { line: line_no, idx };
+            let top = stack.last().map(|(family, _, _)| *family);
+            match c {
+                // an apostrophe like `don’t` or `dogs’`
+                '’' if is_apostrophe(&chars, idx, top == Some(Family::Single)) => {}
+                // curly quotes are often typed the wrong way around, so they pair like straight ones
+                '"' | '＂' | '“' | '”' if top == Some(Family::Double) => {
+                    stack.pop();
+                }
+                '‘' | '’' if top == Some(Family::Single) => {
+                    stack.pop();
+                }
+                '"' | '＂' | '“' | '”' => stack.push((Family::Double, langs[idx], quote)),
+                '‘' | '’' => stack.push((Family::Single, langs[idx], quote)),
+                // corner brackets are never mistaken for each other
+                '「' => stack.push((Family::Corner, langs[idx], quote)),
+                '『' => stack.push((Family::SingleCorner, langs[idx], quote)),
+                '」' if top == Some(Family::Corner) => {
+                    stack.pop();
+                }
+                '』' if top == Some(Family::SingleCorner) => {
+                    stack.pop();
+                }
+                '」' | '』' => self.unbalanced.push(quote),
+                _ => {}
+            }
+        }
+    }
+
+    /// end the paragraph, returning the quotes which cannot be paired in the order they appear
+    pub(crate) fn finish(&mut self) -> Vec<Unbalanced> {
+        let mut unbalanced = std::mem::take(&mut self.unbalanced);
+        unbalanced.extend(self.stack.drain(..).map(|(_, _, quote)| quote));
+        unbalanced.sort_by_key(|it| (it.line, it.idx));
+        unbalanced
+    }
+}
+
+/// whether the quote passes have anything to do on the line, the other lines are normalized the
+/// same whichever quotes are left open around them
+pub(crate) fn has_quotes(line: &str) -> bool {
+    line.contains(['"', '“', '”', '‘', '’'])
+}
+
+/// whether the `’` at `idx` is an apostrophe like `don’t` or `dogs’` rather than a closing quote,
+/// `single_open` tells whether the innermost open quote is a single one
+pub(crate) fn is_apostrophe(chars: &[char], idx: usize, single_open: bool) -> bool {
+    let after_letter = idx > 0 && chars[idx - 1].is_ascii_alphabetic();
+    let before_letter = chars.get(idx + 1).is_some_and(|c| c.is_ascii_alphabetic());
+    after_letter && (before_letter || !single_open)
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "markdown")]
+    use crate::normalize_markdown;
+    use crate::quote::{pair_quotes, Unbalanced};
+    use crate::{lint, normalize, NormalizeOptions};
+
+    #[test]
+    fn should_pair_quotes_across_lines() {
+        assert_eq!(
+            "他说：“这是第一行\n这是第二行”，然后走了。",
+            normalize("他说:\"这是第一行\n这是第二行\",然后走了.")
+        );
+        assert_eq!(
+            "他说：“她说‘你好’，然后走了”",
+            normalize("他说:\"她说‘你好’,然后走了\"")
+        );
+        let options = NormalizeOptions::builder().preserve_layout(true).build();
+        assert_eq!(
+            "“第一段\n结束”\n\n“第二段”\n",
+            crate::normalize_with("\"第一段\n结束\"\n\n\"第二段\"\n", &options)
+        );
+    }
+
+    #[cfg(feature = "markdown")]
+    #[test]
+    fn should_pair_quotes_across_markdown_paragraphs() {
+        assert_eq!(
+            "他说：“这是\n第二行”\n\n“第二段”\n",
+            normalize_markdown("他说:\"这是\n第二行\"\n\n\"第二段\"\n")
+        );
+    }
+
+    #[test]
+    fn should_not_pair_apostrophes() {
+        assert_eq!(
+            "我觉得 it’s 很好，他说‘好’",
+            normalize("我觉得it’s很好，他说‘好’")
+        );
+        assert_eq!("他说“don’t”吧", normalize("他说“don’t”吧"));
+        assert_eq!(
+            "I don't know, 'he said' ok",
+            normalize("I don’t know, ‘he said’ ok")
+        );
+    }
+
+    #[test]
+    fn should_leave_unbalanced_quotes() {
+        assert_eq!("他说：\"你好，\"再见\"", normalize("他说:\"你好,\"再见\""));
+        let (_, unbalanced) = pair_quotes(["a”b", "", "“c"]);
+        assert_eq!(
+            vec![
+                Unbalanced { line: 0, idx: 1 },
+                Unbalanced { line: 2, idx: 0 }
+            ],
+            unbalanced
+        );
+        assert!(pair_quotes(["don’t 「引用『嵌套』」"]).1.is_empty());
+
+        let diagnostics = lint("他说:\"你好\"再见\"");
+        let diagnostic = diagnostics
+            .iter()
+            .find(|it| it.rule == "quote/unbalanced")
+            .unwrap();
+        assert_eq!(21..22, diagnostic.columns);
+        assert!(!diagnostic.fixed);
+    }
+}
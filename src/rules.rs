@@ -0,0 +1,522 @@
+use crate::{
+    digit, is_en_char, is_en_left_punc, is_en_letter, is_en_middle_punc, is_en_right_punc,
+    is_en_right_punc_digit, is_zh_char, is_zh_letter, is_zh_punc,
+};
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+
+/// what happens between two neighbours matching a rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpaceAction {
+    /// remove the space between them
+    Remove,
+    /// insert a space between them
+    Add,
+    /// insert a space between them once the other passes are done, only when `minor_space` is
+    /// enabled
+    Minor,
+    /// leave them as they are, the rules after it are not tried
+    Keep,
+}
+
+impl SpaceAction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SpaceAction::Remove => "remove",
+            SpaceAction::Add => "add",
+            SpaceAction::Minor => "minor",
+            SpaceAction::Keep => "keep",
+        }
+    }
+}
+
+/// a class of characters a spacing rule matches, either a built-in one or a named closure
+#[derive(Clone)]
+pub enum CharClass {
+    ZhChar,
+    ZhLetter,
+    ZhPunc,
+    EnChar,
+    EnLetter,
+    EnLeftPunc,
+    EnRightPunc,
+    EnMiddlePunc,
+    /// the english right punctuations which are followed by a space before a digit
+    EnRightPuncDigit,
+    Digit,
+    Custom(&'static str, Arc<dyn Fn(char) -> bool + Send + Sync>),
+}
+
+impl CharClass {
+    pub fn custom(name: &'static str, f: impl Fn(char) -> bool + Send + Sync + 'static) -> Self {
+        CharClass::Custom(name, Arc::new(f))
+    }
+
+    /// the name used in rule ids, like `zh-letter`
+    pub fn name(&self) -> &'static str {
+        match self {
+            CharClass::ZhChar => "zh-char",
+            CharClass::ZhLetter => "zh-letter",
+            CharClass::ZhPunc => "zh-punc",
+            CharClass::EnChar => "en-char",
+            CharClass::EnLetter => "en-letter",
+            CharClass::EnLeftPunc => "en-left-punc",
+            CharClass::EnRightPunc => "en-right-punc",
+            CharClass::EnMiddlePunc => "en-middle-punc",
+            CharClass::EnRightPuncDigit => "en-right-punc-digit",
+            CharClass::Digit => "digit",
+            CharClass::Custom(name, _) => name,
+        }
+    }
+
+    /// the bit of a built-in class in [`Classified`]
+    fn bit(&self) -> Option<u16> {
+        let idx = match self {
+            CharClass::ZhChar => 0,
+            CharClass::ZhLetter => 1,
+            CharClass::ZhPunc => 2,
+            CharClass::EnChar => 3,
+            CharClass::EnLetter => 4,
+            CharClass::EnLeftPunc => 5,
+            CharClass::EnRightPunc => 6,
+            CharClass::EnMiddlePunc => 7,
+            CharClass::EnRightPuncDigit => 8,
+            CharClass::Digit => 9,
+            CharClass::Custom(..) => return None,
+        };
+        Some(1 << idx)
+    }
+
+    pub fn contains(&self, c: &char) -> bool {
+        match self {
+            CharClass::ZhChar => is_zh_char(c),
+            CharClass::ZhLetter => is_zh_letter(c),
+            CharClass::ZhPunc => is_zh_punc(c),
+            CharClass::EnChar => is_en_char(c),
+            CharClass::EnLetter => is_en_letter(c),
+            CharClass::EnLeftPunc => is_en_left_punc(c),
+            CharClass::EnRightPunc => is_en_right_punc(c),
+            CharClass::EnMiddlePunc => is_en_middle_punc(c),
+            CharClass::EnRightPuncDigit => is_en_right_punc_digit(c),
+            CharClass::Digit => digit(c),
+            CharClass::Custom(_, f) => f(*c),
+        }
+    }
+}
+
+impl fmt::Debug for CharClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// custom classes are only equal to themselves
+impl PartialEq for CharClass {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (CharClass::Custom(a, f), CharClass::Custom(b, g)) => a == b && Arc::ptr_eq(f, g),
+            (CharClass::Custom(..), _) | (_, CharClass::Custom(..)) => false,
+            _ => self.name() == other.name(),
+        }
+    }
+}
+
+impl Eq for CharClass {}
+
+/// a char along with the built-in classes it belongs to, so they are looked up once for all
+/// the rules rather than once for every rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Classified {
+    pub(crate) c: char,
+    bits: u16,
+}
+
+impl Classified {
+    pub(crate) fn new(c: char) -> Self {
+        static ASCII: OnceLock<[u16; 128]> = OnceLock::new();
+        // most chars are ascii or common han ideographs
+        if c.is_ascii() {
+            let table = ASCII.get_or_init(|| {
+                let mut table = [0; 128];
+                for (c, bits) in ('\0'..='\x7f').zip(&mut table) {
+                    *bits = Self::lookup(c).bits;
+                }
+                table
+            });
+            return Self {
+                c,
+                bits: table[c as usize],
+            };
+        }
+        if ('\u{4e00}'..='\u{9fff}').contains(&c) {
+            let bits = [CharClass::ZhChar, CharClass::ZhLetter]
+                .iter()
+                .filter_map(CharClass::bit)
+                .sum();
+            return Self { c, bits };
+        }
+        Self::lookup(c)
+    }
+
+    fn lookup(c: char) -> Self {
+        use CharClass::*;
+        let bits = [
+            ZhChar,
+            ZhLetter,
+            ZhPunc,
+            EnChar,
+            EnLetter,
+            EnLeftPunc,
+            EnRightPunc,
+            EnMiddlePunc,
+            EnRightPuncDigit,
+            Digit,
+        ]
+        .iter()
+        .filter(|class| class.contains(&c))
+        .filter_map(CharClass::bit)
+        .sum();
+        Self { c, bits }
+    }
+
+    fn is(&self, class: &CharClass) -> bool {
+        match class.bit() {
+            Some(bit) => self.bits & bit != 0,
+            None => class.contains(&self.c),
+        }
+    }
+}
+
+/// a rule deciding the space between a left and a right character
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpaceRule {
+    /// stable id reported by `lint`, like `space/add/en-letter+en-left-punc`
+    pub id: &'static str,
+    pub left: CharClass,
+    pub right: CharClass,
+    pub action: SpaceAction,
+}
+
+impl SpaceRule {
+    pub fn new(id: &'static str, left: CharClass, right: CharClass, action: SpaceAction) -> Self {
+        Self {
+            id,
+            left,
+            right,
+            action,
+        }
+    }
+
+    fn matches(&self, left: &Classified, right: &Classified) -> bool {
+        left.is(&self.left) && right.is(&self.right)
+    }
+}
+
+/// the ordered spacing rules used by `normalize`, starting from the built-in ones.
+///
+/// for every pair of neighbours the first rule matching them with the action being applied, or
+/// with [`SpaceAction::Keep`], decides.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleSet {
+    rules: Vec<SpaceRule>,
+}
+
+impl Default for RuleSet {
+    fn default() -> Self {
+        use CharClass::*;
+        use SpaceAction::*;
+        let rules = [
+            ("space/remove/zh-char+zh-char", ZhChar, ZhChar, Remove),
+            ("space/remove/zh-char+digit", ZhChar, Digit, Remove),
+            ("space/remove/digit+zh-char", Digit, ZhChar, Remove),
+            (
+                "space/remove/zh-letter+en-letter",
+                ZhLetter,
+                EnLetter,
+                Remove,
+            ),
+            (
+                "space/remove/en-letter+zh-letter",
+                EnLetter,
+                ZhLetter,
+                Remove,
+            ),
+            (
+                "space/remove/zh-letter+en-right-punc",
+                ZhLetter,
+                EnRightPunc,
+                Remove,
+            ),
+            (
+                "space/remove/en-left-punc+zh-letter",
+                EnLeftPunc,
+                ZhLetter,
+                Remove,
+            ),
+            ("space/remove/zh-punc+en-char", ZhPunc, EnChar, Remove),
+            ("space/remove/en-char+zh-punc", EnChar, ZhPunc, Remove),
+            (
+                "space/remove/en-letter+en-right-punc",
+                EnLetter,
+                EnRightPunc,
+                Remove,
+            ),
+            (
+                "space/remove/en-left-punc+en-letter",
+                EnLeftPunc,
+                EnLetter,
+                Remove,
+            ),
+            (
+                "space/remove/en-left-punc+en-left-punc",
+                EnLeftPunc,
+                EnLeftPunc,
+                Remove,
+            ),
+            (
+                "space/remove/en-left-punc+en-right-punc",
+                EnLeftPunc,
+                EnRightPunc,
+                Remove,
+            ),
+            (
+                "space/remove/en-left-punc+en-middle-punc",
+                EnLeftPunc,
+                EnMiddlePunc,
+                Remove,
+            ),
+            (
+                "space/remove/en-right-punc+en-right-punc",
+                EnRightPunc,
+                EnRightPunc,
+                Remove,
+            ),
+            (
+                "space/remove/en-middle-punc+en-right-punc",
+                EnMiddlePunc,
+                EnRightPunc,
+                Remove,
+            ),
+            (
+                "space/remove/en-middle-punc+en-middle-punc",
+                EnMiddlePunc,
+                EnMiddlePunc,
+                Remove,
+            ),
+            (
+                "space/remove/digit+en-right-punc",
+                Digit,
+                EnRightPunc,
+                Remove,
+            ),
+            ("space/remove/en-left-punc+digit", EnLeftPunc, Digit, Remove),
+            (
+                "space/add/zh-letter+en-left-punc",
+                ZhLetter,
+                EnLeftPunc,
+                Add,
+            ),
+            (
+                "space/add/zh-letter+en-middle-punc",
+                ZhLetter,
+                EnMiddlePunc,
+                Add,
+            ),
+            (
+                "space/add/en-right-punc+zh-letter",
+                EnRightPunc,
+                ZhLetter,
+                Add,
+            ),
+            (
+                "space/add/en-middle-punc+zh-letter",
+                EnMiddlePunc,
+                ZhLetter,
+                Add,
+            ),
+            (
+                "space/add/en-letter+en-left-punc",
+                EnLetter,
+                EnLeftPunc,
+                Add,
+            ),
+            (
+                "space/add/en-letter+en-middle-punc",
+                EnLetter,
+                EnMiddlePunc,
+                Add,
+            ),
+            (
+                "space/add/en-right-punc+en-letter",
+                EnRightPunc,
+                EnLetter,
+                Add,
+            ),
+            (
+                "space/add/en-middle-punc+en-letter",
+                EnMiddlePunc,
+                EnLetter,
+                Add,
+            ),
+            (
+                "space/add/en-right-punc+en-left-punc",
+                EnRightPunc,
+                EnLeftPunc,
+                Add,
+            ),
+            (
+                "space/add/en-right-punc+en-middle-punc",
+                EnRightPunc,
+                EnMiddlePunc,
+                Add,
+            ),
+            (
+                "space/add/en-middle-punc+en-left-punc",
+                EnMiddlePunc,
+                EnLeftPunc,
+                Add,
+            ),
+            ("space/add/digit+en-left-punc", Digit, EnLeftPunc, Add),
+            ("space/add/digit+en-middle-punc", Digit, EnMiddlePunc, Add),
+            (
+                "space/add/en-right-punc-digit+digit",
+                EnRightPuncDigit,
+                Digit,
+                Add,
+            ),
+            ("space/add/en-middle-punc+digit", EnMiddlePunc, Digit, Add),
+            ("space/minor/zh-letter+en-letter", ZhLetter, EnLetter, Minor),
+            ("space/minor/en-letter+zh-letter", EnLetter, ZhLetter, Minor),
+            ("space/minor/zh-letter+digit", ZhLetter, Digit, Minor),
+            ("space/minor/digit+zh-letter", Digit, ZhLetter, Minor),
+        ];
+        Self {
+            rules: rules
+                .into_iter()
+                .map(|(id, left, right, action)| SpaceRule::new(id, left, right, action))
+                .collect(),
+        }
+    }
+}
+
+impl RuleSet {
+    /// a rule set without any rule, spaces are left untouched
+    pub fn empty() -> Self {
+        Self { rules: vec![] }
+    }
+
+    pub fn rules(&self) -> &[SpaceRule] {
+        &self.rules
+    }
+
+    /// the index of the rule with the id
+    pub fn position(&self, id: &str) -> Option<usize> {
+        self.rules.iter().position(|rule| rule.id == id)
+    }
+
+    /// append a rule, which is tried after all the others
+    pub fn push(&mut self, rule: SpaceRule) -> &mut Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// insert a rule at the index, shifting the rules after it
+    pub fn insert(&mut self, index: usize, rule: SpaceRule) -> &mut Self {
+        self.rules.insert(index, rule);
+        self
+    }
+
+    /// remove the rule with the id
+    pub fn remove(&mut self, id: &str) -> Option<SpaceRule> {
+        let index = self.position(id)?;
+        Some(self.rules.remove(index))
+    }
+
+    pub fn retain(&mut self, f: impl FnMut(&SpaceRule) -> bool) -> &mut Self {
+        self.rules.retain(f);
+        self
+    }
+
+    /// the rule deciding to apply the action between the neighbours
+    pub(crate) fn find(
+        &self,
+        action: SpaceAction,
+        left: &char,
+        right: &char,
+    ) -> Option<&SpaceRule> {
+        self.find_classified(action, &Classified::new(*left), &Classified::new(*right))
+    }
+
+    /// same as [`RuleSet::find`], for neighbours whose classes are already looked up
+    pub(crate) fn find_classified(
+        &self,
+        action: SpaceAction,
+        left: &Classified,
+        right: &Classified,
+    ) -> Option<&SpaceRule> {
+        self.rules
+            .iter()
+            .filter(|rule| rule.action == action || rule.action == SpaceAction::Keep)
+            .find(|rule| rule.matches(left, right))
+            .filter(|rule| rule.action == action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rules::Classified;
+    use crate::{
+        normalize, normalize_with, CharClass, NormalizeOptions, RuleSet, SpaceAction, SpaceRule,
+    };
+
+    #[test]
+    fn should_classify_chars_the_same_as_the_classes() {
+        for c in ('\0'..='\u{ffff}').chain(['\u{20000}', '\u{f0000}']) {
+            assert_eq!(Classified::lookup(c), Classified::new(c), "{:?}", c);
+        }
+    }
+
+    #[test]
+    fn should_keep_default_behaviour() {
+        let options = NormalizeOptions::builder()
+            .rules(RuleSet::default())
+            .build();
+        let content = "中文abc,中文 (abc)1+2";
+        assert_eq!(normalize(content), normalize_with(content, &options));
+        assert_eq!(38, RuleSet::default().rules().len());
+    }
+
+    #[test]
+    fn should_apply_custom_rules() {
+        let mut rules = RuleSet::default();
+        // keep chinese and english words together, before the minor rule is tried
+        rules.insert(
+            0,
+            SpaceRule::new(
+                "space/keep/zh-letter+en-letter",
+                CharClass::ZhLetter,
+                CharClass::EnLetter,
+                SpaceAction::Keep,
+            ),
+        );
+        rules.push(SpaceRule::new(
+            "space/minor/zh-letter+emoji",
+            CharClass::ZhLetter,
+            CharClass::custom("emoji", |c| ('\u{1f300}'..='\u{1faff}').contains(&c)),
+            SpaceAction::Minor,
+        ));
+        let options = NormalizeOptions::builder().rules(rules.clone()).build();
+        assert_eq!(
+            "中文abc 中文 🎉",
+            normalize_with("中文abc 中文🎉", &options)
+        );
+
+        assert!(rules.remove("space/minor/zh-letter+digit").is_some());
+        assert!(rules.remove("space/minor/zh-letter+digit").is_none());
+        let options = NormalizeOptions::builder().rules(rules).build();
+        assert_eq!("中文123", normalize_with("中文123", &options));
+
+        let options = NormalizeOptions::builder().rules(RuleSet::empty()).build();
+        assert_eq!("中文 中文abc", normalize_with("中文 中文abc", &options));
+    }
+}
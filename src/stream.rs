@@ -0,0 +1,177 @@
+use crate::directive::Directives;
+use crate::{split_lines_with, NormalizeOptions};
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Write};
+
+/// the most lines held at once, a paragraph longer than that is normalized in several parts
+/// and its quotes are only paired inside each part
+pub(crate) const MAX_PARAGRAPH_LINES: usize = 1024;
+
+/// normalize content as it arrives, paragraph by paragraph, so only one paragraph is held in
+/// memory at a time.
+///
+/// quotes are paired inside a paragraph and ignore directives apply to the lines after them,
+/// so the output is the same as [`normalize_with`](crate::normalize_with) on the whole content,
+/// unless a paragraph runs over 1024 lines: it is then normalized in parts and a quote open at
+/// the end of a part is not paired with the next one.
+///
+/// ```
+/// use chineseword::Normalizer;
+///
+/// let mut output = vec![];
+/// Normalizer::default()
+///     .normalize_io("中文abc\n\n他说:\"你好\n再见\"".as_bytes(), &mut output)
+///     .unwrap();
+/// assert_eq!("中文 abc\n\n他说：“你好\n再见”", String::from_utf8(output).unwrap());
+/// ```
+#[derive(Debug, Default)]
+pub struct Normalizer {
+    options: NormalizeOptions,
+    directives: Directives,
+    /// the lines of the current paragraph, with their line endings
+    paragraph: String,
+    lines: usize,
+    /// whether a line has been written, lines are separated by `\n` unless `preserve_layout`
+    /// is set
+    written: bool,
+}
+
+impl Normalizer {
+    pub fn new(options: NormalizeOptions) -> Self {
+        Self {
+            options,
+            ..Self::default()
+        }
+    }
+
+    /// normalize everything read from the reader into the writer.
+    ///
+    /// the input must be utf-8, otherwise an `InvalidData` error is returned.
+    pub fn normalize_io(
+        &mut self,
+        mut reader: impl BufRead,
+        mut writer: impl Write,
+    ) -> io::Result<()> {
+        let mut line = String::new();
+        while reader.read_line(&mut line)? > 0 {
+            for (text, ending) in self.push(&line) {
+                self.write(&mut writer, &text, &ending)?;
+            }
+            line.clear();
+        }
+        for (text, ending) in self.finish() {
+            self.write(&mut writer, &text, &ending)?;
+        }
+        writer.flush()
+    }
+
+    /// normalize the lines of an iterator, which hold no line ending. a normalized line is
+    /// yielded for every line once its paragraph is complete.
+    pub fn lines<I: Iterator<Item = String>>(self, lines: I) -> NormalizeLines<I> {
+        NormalizeLines {
+            normalizer: self,
+            lines,
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn write(&mut self, writer: &mut impl Write, text: &str, ending: &str) -> io::Result<()> {
+        if self.options.preserve_layout {
+            writer.write_all(text.as_bytes())?;
+            return writer.write_all(ending.as_bytes());
+        }
+        if std::mem::replace(&mut self.written, true) {
+            writer.write_all(b"\n")?;
+        }
+        writer.write_all(text.as_bytes())
+    }
+
+    /// add a line along with its line ending, the lines of the paragraph are returned once it
+    /// is complete
+    fn push(&mut self, line: &str) -> Vec<(String, String)> {
+        self.paragraph.push_str(line);
+        self.lines += 1;
+        if line.trim().is_empty() || self.lines >= MAX_PARAGRAPH_LINES {
+            return self.finish();
+        }
+        vec![]
+    }
+
+    /// the normalized lines of the paragraph held so far, with their line endings
+    fn finish(&mut self) -> Vec<(String, String)> {
+        let paragraph = std::mem::take(&mut self.paragraph);
+        self.lines = 0;
+        split_lines_with(&paragraph, &self.options, &mut self.directives)
+            .iter()
+            .map(|line| (line.normalize(&self.options), line.ending.to_string()))
+            .collect()
+    }
+}
+
+/// the iterator returned by [`Normalizer::lines`]
+#[derive(Debug)]
+pub struct NormalizeLines<I> {
+    normalizer: Normalizer,
+    lines: I,
+    pending: VecDeque<String>,
+}
+
+impl<I: Iterator<Item = String>> Iterator for NormalizeLines<I> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        while self.pending.is_empty() {
+            let lines = match self.lines.next() {
+                Some(line) => self.normalizer.push(&format!("{}\n", line)),
+                None if self.normalizer.lines > 0 => self.normalizer.finish(),
+                None => return None,
+            };
+            self.pending.extend(lines.into_iter().map(|(text, _)| text));
+        }
+        self.pending.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stream::MAX_PARAGRAPH_LINES;
+    use crate::{normalize_with, NormalizeOptions, Normalizer};
+
+    #[test]
+    fn should_stream_the_same_as_normalize() {
+        let content = "  中文abc\r\n\n<!-- chineseword-disable -->\n中文abc\n\n<!-- chineseword-enable -->\n他说:\"你好\n再见\"\n\n";
+        for preserve_layout in [false, true] {
+            let options = NormalizeOptions::builder()
+                .preserve_layout(preserve_layout)
+                .build();
+            let mut output = vec![];
+            Normalizer::new(options.clone())
+                .normalize_io(content.as_bytes(), &mut output)
+                .unwrap();
+            assert_eq!(
+                normalize_with(content, &options),
+                String::from_utf8(output).unwrap()
+            );
+        }
+
+        let mut output = vec![];
+        assert!(Normalizer::default()
+            .normalize_io(&[0xff, b'\n'][..], &mut output)
+            .is_err());
+    }
+
+    #[test]
+    fn should_normalize_lines_lazily() {
+        let lines = ["他说:\"你好", "再见\"", "", "中文abc"].map(String::from);
+        let normalized = Normalizer::default()
+            .lines(lines.into_iter())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["他说：“你好", "再见”", "", "中文 abc"], normalized);
+
+        // a paragraph too long to be held is normalized in parts
+        let lines = std::iter::repeat_n("中文abc".to_string(), MAX_PARAGRAPH_LINES * 2 + 1);
+        let mut normalized = Normalizer::default().lines(lines);
+        assert_eq!(Some("中文 abc".to_string()), normalized.next());
+        assert_eq!(MAX_PARAGRAPH_LINES * 2, normalized.count());
+    }
+}
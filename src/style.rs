@@ -0,0 +1,221 @@
+use crate::lang::char_langs;
+use crate::{is_zh_letter, Lang, NormalizeOptions};
+use std::ops::Range;
+
+pub(crate) static PERIOD_AFTER_MARK: &str = "punc/style/period-after-mark";
+pub(crate) static REPEATED: &str = "punc/style/repeated";
+pub(crate) static NESTED_TITLE: &str = "punc/style/nested-title";
+pub(crate) static ENUMERATION_COMMA: &str = "punc/style/enumeration-comma";
+pub(crate) static MIXED_WIDTH: &str = "punc/style/mixed-width";
+
+/// marks which are never doubled, the full stop is only merged when there are two of them since
+/// three or more make an ellipsis
+static REPEATED_PUNC: [char; 4] = ['，', '、', '；', '：'];
+
+/// conjunctions joining the last item of an enumeration, no enumeration comma goes before them.
+/// they also start words like `和谐` or `与会`, so the comma is only reported
+static CONJUNCTIONS: [char; 4] = ['和', '与', '及', '或'];
+
+/// half-width marks and their full-width counterparts
+static WIDTH_PAIRS: [(char, char); 7] = [
+    (',', '，'),
+    (';', '；'),
+    (':', '：'),
+    ('?', '？'),
+    ('!', '！'),
+    ('(', '（'),
+    (')', '）'),
+];
+
+/// fix the violations of GB/T 15834-2011 having a single obvious correction: a full stop after
+/// `？` or `！`, doubled marks and `《》` nested in `《》`
+pub(crate) fn correct_punc_style(chars: Vec<char>, options: &NormalizeOptions) -> Vec<char> {
+    let period_after_mark = options.is_enabled(PERIOD_AFTER_MARK);
+    let repeated = options.is_enabled(REPEATED);
+    let mut ret: Vec<char> = Vec::with_capacity(chars.len());
+    for (idx, c) in chars.iter().enumerate() {
+        let prev = ret.last();
+        let next = chars.get(idx + 1);
+        let redundant = match c {
+            '。' => {
+                (period_after_mark && matches!(prev, Some('？' | '！')))
+                    || (repeated
+                        && prev == Some(&'。')
+                        && (idx < 2 || chars[idx - 2] != '。')
+                        && next != Some(&'。'))
+            }
+            _ => repeated && REPEATED_PUNC.contains(c) && prev == Some(c),
+        };
+        if !redundant {
+            ret.push(*c);
+        }
+    }
+    if options.is_enabled(NESTED_TITLE) {
+        nest_title_marks(&mut ret);
+    }
+    ret
+}
+
+/// a title inside a title is marked by `〈〉`, only pairs inside a closed title are changed
+fn nest_title_marks(chars: &mut [char]) {
+    let mut opens: Vec<usize> = vec![];
+    let mut nested = vec![];
+    for (idx, c) in chars.iter().enumerate() {
+        match c {
+            '《' => opens.push(idx),
+            '》' => {
+                if let Some(open) = opens.pop() {
+                    if let Some(outer) = opens.last() {
+                        nested.push((*outer, open, idx));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    // the opens left are unclosed, the enclosing title has to be closed as well
+    let mut unclosed = vec![false; chars.len()];
+    for open in opens {
+        unclosed[open] = true;
+    }
+    for (outer, open, close) in nested {
+        if !unclosed[outer] {
+            chars[open] = '〈';
+            chars[close] = '〉';
+        }
+    }
+}
+
+/// the rule of a change made by [`correct_punc_style`], given the line before the change, the
+/// replaced range and the replacement
+pub(crate) fn style_rule(before: &[char], old: Range<usize>, new: &[char]) -> &'static str {
+    if new.contains(&'〈') || new.contains(&'〉') {
+        return NESTED_TITLE;
+    }
+    match (
+        old.start.checked_sub(1).map(|idx| before[idx]),
+        &before[old],
+    ) {
+        (Some('？' | '！'), ['。']) => PERIOD_AFTER_MARK,
+        _ => REPEATED,
+    }
+}
+
+/// violations which need a human to decide, as the char range, the rule id and the suggested
+/// replacement: half-width marks in a chinese sentence using full-width ones, a comma between
+/// items of an enumeration and an enumeration comma before what may be a conjunction
+pub(crate) fn check_punc_style(chars: &[char]) -> Vec<(Range<usize>, &'static str, String)> {
+    let mut ret = vec![];
+    let langs = char_langs(chars);
+    let mut start = 0;
+    for (idx, c) in chars.iter().enumerate() {
+        if idx + 1 == chars.len() || matches!(c, '。' | '？' | '！') {
+            check_mixed_width(chars, &langs, start..idx + 1, &mut ret);
+            start = idx + 1;
+        }
+    }
+    for (idx, c) in chars.iter().enumerate() {
+        if *c == '、'
+            && chars
+                .get(idx + 1)
+                .is_some_and(|next| CONJUNCTIONS.contains(next))
+        {
+            ret.push((idx..idx + 1, ENUMERATION_COMMA, String::new()));
+            continue;
+        }
+        if *c != '，' {
+            continue;
+        }
+        let left = item_len(chars[..idx].iter().rev());
+        let right = item_len(chars[idx + 1..].iter());
+        let listed = (1..=4).contains(&left)
+            && (1..=4).contains(&right)
+            && idx > left
+            && chars[idx - left - 1] == '、'
+            && matches!(chars.get(idx + right + 1), Some('、' | '等'));
+        if listed {
+            ret.push((idx..idx + 1, ENUMERATION_COMMA, "、".to_string()));
+        }
+    }
+    ret.sort_by_key(|(range, _, _)| range.start);
+    ret
+}
+
+fn check_mixed_width(
+    chars: &[char],
+    langs: &[Lang],
+    sentence: Range<usize>,
+    ret: &mut Vec<(Range<usize>, &'static str, String)>,
+) {
+    let full_width = chars[sentence.clone()]
+        .iter()
+        .any(|c| *c == '。' || WIDTH_PAIRS.iter().any(|(_, full)| full == c));
+    let zh = chars[sentence.clone()].iter().any(is_zh_letter);
+    if !full_width || !zh {
+        return;
+    }
+    for idx in sentence {
+        if langs[idx] != Lang::Zh {
+            continue;
+        }
+        if let Some((_, full)) = WIDTH_PAIRS.iter().find(|(half, _)| *half == chars[idx]) {
+            ret.push((idx..idx + 1, MIXED_WIDTH, full.to_string()));
+        }
+    }
+}
+
+/// the number of chinese letters before the next punctuation
+fn item_len<'a>(chars: impl Iterator<Item = &'a char>) -> usize {
+    chars.take_while(|c| is_zh_letter(c)).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{lint, normalize};
+
+    #[test]
+    fn should_fix_punctuation_style() {
+        assert_eq!("真的吗？是的！", normalize("真的吗？。是的！。"));
+        assert_eq!("中文，中文。中文", normalize("中文，，中文。。中文"));
+        assert_eq!("中文……", normalize("中文。。。"));
+        assert_eq!("《读〈呐喊〉有感》", normalize("《读《呐喊》有感》"));
+        assert_eq!("《呐喊》《彷徨》", normalize("《呐喊》《彷徨》"));
+    }
+
+    #[test]
+    fn should_keep_enumeration_comma_before_words() {
+        for content in [
+            "苹果、和谐的社会",
+            "会议、与会人员",
+            "书、及时雨",
+            "猫、或许狗",
+        ] {
+            assert_eq!(content, normalize(content));
+        }
+    }
+
+    #[test]
+    fn should_report_punctuation_style() {
+        let diagnostics = lint("中文 abc, 中文，中文。");
+        assert_eq!(1, diagnostics.len());
+        assert_eq!("punc/style/mixed-width", diagnostics[0].rule);
+        assert_eq!(",", diagnostics[0].original);
+        assert_eq!("，", diagnostics[0].replacement);
+        assert!(!diagnostics[0].fixed);
+
+        let diagnostics = lint("苹果、香蕉，橘子、梨等水果");
+        assert_eq!(1, diagnostics.len());
+        assert_eq!("punc/style/enumeration-comma", diagnostics[0].rule);
+        assert_eq!(15..18, diagnostics[0].columns);
+
+        let diagnostics = lint("苹果、香蕉、和梨");
+        assert_eq!(1, diagnostics.len());
+        assert_eq!("punc/style/enumeration-comma", diagnostics[0].rule);
+        assert_eq!("", diagnostics[0].replacement);
+        assert!(!diagnostics[0].fixed);
+
+        let diagnostics = lint("真的吗？。");
+        assert_eq!("punc/style/period-after-mark", diagnostics[0].rule);
+        assert!(diagnostics[0].fixed);
+    }
+}